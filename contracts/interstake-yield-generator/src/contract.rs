@@ -3,7 +3,7 @@ use cosmwasm_std::entry_point;
 use cosmwasm_std::{
     coin, to_binary, Addr, BankMsg, Binary, Coin, Decimal, DelegationResponse, Deps, DepsMut,
     DistributionMsg, Env, MessageInfo, Order, Order::Ascending, QueryRequest, Response, StakingMsg,
-    StakingQuery, StdResult, Timestamp, Uint128,
+    StakingQuery, StdResult, SubMsg, Timestamp, Uint128, WasmMsg,
 };
 use cw2::set_contract_version;
 use cw_utils::{ensure_from_older_version, Duration, Expiration};
@@ -12,21 +12,25 @@ use crate::error::ContractError;
 
 use crate::migration::migrate_config;
 use crate::msg::{
-    ClaimsResponse, ConfigResponse, DelegateResponse, DelegatedResponse, ExecuteMsg,
-    InstantiateMsg, LastPaymentBlockResponse, MigrateMsg, QueryMsg, RewardResponse,
-    TotalDelegatedResponse,
+    ClaimsResponse, ConfigResponse, ConfigUpdate, DelegateResponse, DelegatedResponse, ExecuteMsg,
+    InstantiateMsg, LastPaymentBlockResponse, MigrateMsg, QueryMsg, RemovalMode, RewardResponse,
+    StakeChange, StakeChangedHookMsg, TotalDelegatedResponse,
 };
 use crate::state::{
-    ClaimDetails, Config, Stake, StakeDetails, CONFIG, LAST_PAYMENT_BLOCK, LATEST_UNBONDING,
-    STAKE_DETAILS, TOTAL, UNBONDING_CLAIMS, VALIDATOR_LIST,
+    ClaimDetails, Config, PayoutMode, Stake, StakeDetails, CONFIG, LAST_PAYMENT_BLOCK,
+    LATEST_UNBONDING, STAKE_DETAILS, TOTAL, UNBONDING_CLAIMS, VALIDATOR_LIST,
 };
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 const CONTRACT_NAME: &str = "crates.io:interstake-yield-generator";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const MIN_EXPIRATION: u64 = 3600 * 24 * 28; // 28 days
+const DEFAULT_REFERRAL_PERIOD: u64 = 3600 * 24 * 365; // 365 days
+const MAX_COMMISSION_PERCENT: u64 = 50; // hard cap for restake and transfer commissions
+const MAX_COMMISSION_CHANGE_PERCENT: u64 = 10; // max commission change within a change window
+const COMMISSION_CHANGE_BLOCKS: u64 = 14_400; // ~1 day at 6s blocks
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -46,27 +50,64 @@ pub fn instantiate(
         denom,
         unbonding_period,
         max_entries,
+        drift_threshold,
+        referral_commission,
+        referral_period,
+        restake_commission_payout,
+        transfer_commission_payout,
+        timelock_delay,
+        vote_quorum,
+        lock_tiers,
+        early_unlock_penalty,
+        early_exit_fee,
+        early_exit_blocks,
+        early_exit_treasury_share,
+        deposit_cap,
+        address_cap,
+        min_delegation,
+        min_undelegation,
     } = msg;
 
     let owner = deps.api.addr_validate(&owner)?;
-    let treasury = deps.api.addr_validate(&treasury)?;
+    let treasury = utils::validate_treasury(deps.api, treasury)?;
 
     let max_entries = max_entries.unwrap_or(7);
-    let unbonding_period = unbonding_period.unwrap_or(MIN_EXPIRATION);
-
-    let (unbonding_period, min_unbonding_cooldown) = (
-        Duration::Time(unbonding_period),
-        Duration::Time(unbonding_period.saturating_div(max_entries)),
-    );
+    let unbonding_period = Duration::Time(unbonding_period.unwrap_or(MIN_EXPIRATION));
+    let min_unbonding_cooldown = utils::unbonding_cooldown(unbonding_period, max_entries)?;
 
     let config = Config {
         owner: owner.clone(),
         treasury,
-        restake_commission,
-        transfer_commission,
+        restake_commission: utils::validate_commission(restake_commission)?,
+        transfer_commission: utils::validate_commission(transfer_commission)?,
         denom: denom.clone(),
         unbonding_period,
         min_unbonding_cooldown,
+        max_entries,
+        drift_threshold: utils::validate_drift_threshold(
+            drift_threshold.unwrap_or(Decimal::percent(5)),
+        )?,
+        referral_commission: utils::validate_ratio(
+            referral_commission.unwrap_or_else(Decimal::zero),
+        )?,
+        referral_period: Duration::Time(referral_period.unwrap_or(DEFAULT_REFERRAL_PERIOD)),
+        restake_commission_payout: restake_commission_payout.unwrap_or(PayoutMode::Liquid),
+        transfer_commission_payout: transfer_commission_payout.unwrap_or(PayoutMode::Stake),
+        timelock_delay: Duration::Time(timelock_delay.unwrap_or_default()),
+        vote_quorum: utils::validate_vote_quorum(vote_quorum.unwrap_or(Decimal::percent(10)))?,
+        lock_tiers: utils::validate_lock_tiers(lock_tiers.unwrap_or_default())?,
+        early_unlock_penalty: utils::validate_unlock_penalty(
+            early_unlock_penalty.unwrap_or(Decimal::percent(10)),
+        )?,
+        early_exit_fee: utils::validate_early_exit_fee(early_exit_fee.unwrap_or_default())?,
+        early_exit_blocks: early_exit_blocks.unwrap_or_default(),
+        early_exit_treasury_share: utils::validate_ratio(
+            early_exit_treasury_share.unwrap_or(Decimal::percent(50)),
+        )?,
+        deposit_cap: deposit_cap.unwrap_or_default(),
+        address_cap: address_cap.unwrap_or_default(),
+        min_delegation: min_delegation.unwrap_or_default(),
+        min_undelegation: min_undelegation.unwrap_or_default(),
     };
     CONFIG.save(deps.storage, &config)?;
 
@@ -86,7 +127,7 @@ pub fn instantiate(
 
     // Initialize last payment block
     LAST_PAYMENT_BLOCK.save(deps.storage, &env.block.height)?;
-    TOTAL.save(deps.storage, &coin(0u128, &denom))?;
+    utils::save_total(deps.storage, env.block.height, &coin(0u128, &denom))?;
 
     Ok(response)
 }
@@ -99,25 +140,15 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::UpdateConfig {
-            owner,
-            treasury,
-            restake_commission,
-            transfer_commission,
-            unbonding_period,
-        } => execute::update_config(
-            deps,
-            info,
-            owner,
-            treasury,
-            restake_commission,
-            transfer_commission,
-            unbonding_period,
-        ),
+        ExecuteMsg::UpdateConfig(update) => execute::update_config(deps, env, info, update),
         ExecuteMsg::UpdateValidatorList { new_validator_list } => {
-            execute::update_validator_list(deps, info, new_validator_list)
+            execute::update_validator_list(deps, env, info, new_validator_list)
+        }
+        ExecuteMsg::Delegate { referrer } => execute::delegate(deps, env, info, referrer),
+        ExecuteMsg::DelegateLocked { duration } => {
+            execute::delegate_locked(deps, env, info, duration)
         }
-        ExecuteMsg::Delegate {} => execute::delegate(deps, env, info),
+        ExecuteMsg::Unlock { id } => execute::unlock(deps, env, info, id),
         ExecuteMsg::Undelegate { amount } => execute::queue_undelegate(deps, env, info, amount),
         ExecuteMsg::Claim {} => execute::claim(deps, env, info),
         ExecuteMsg::Restake {} => execute::restake(deps, env),
@@ -133,44 +164,188 @@ pub fn execute(
             amount,
             commission_address,
         ),
-        ExecuteMsg::UndelegateAll {} => execute::undelegate_all(deps, env, info),
-        ExecuteMsg::UpdateAllowedAddr { address, expires } => {
-            execute::update_allowed_address(deps, env, info, address, expires)
+        ExecuteMsg::UndelegateAll { resumable } => {
+            execute::undelegate_all(deps, env, info, resumable.unwrap_or(false))
         }
+        ExecuteMsg::Resume {} => execute::resume(deps, env, info),
+        ExecuteMsg::UpdateAllowedAddr {
+            address,
+            expires,
+            commission_ratio,
+            commission_cap,
+        } => execute::update_allowed_address(
+            deps,
+            env,
+            info,
+            address,
+            expires,
+            commission_ratio,
+            commission_cap,
+        ),
         ExecuteMsg::RemoveAllowedAddr { address } => {
-            execute::remove_allowed_address(deps, info, address)
+            execute::remove_allowed_address(deps, env, info, address)
         }
         ExecuteMsg::BatchUnbond {} => execute::batch_unbond(deps, env, info),
+        ExecuteMsg::Rebond {} => execute::rebond(deps, env),
+        ExecuteMsg::Rebalance {} => execute::rebalance(deps, env),
+        ExecuteMsg::WithdrawCommission { amount } => {
+            execute::withdraw_commission(deps, env, info, amount)
+        }
+        ExecuteMsg::RemoveValidator { validator, mode } => {
+            execute::remove_validator(deps, env, info, validator, mode)
+        }
+        ExecuteMsg::ProposeConfigChange(update) => {
+            execute::propose_config_change(deps, env, info, update)
+        }
+        ExecuteMsg::ProposeValidatorList { new_validator_list } => {
+            execute::propose_validator_list(deps, env, info, new_validator_list)
+        }
+        ExecuteMsg::ExecutePending { id } => execute::execute_pending(deps, env, info, id),
+        ExecuteMsg::CancelPending { id } => execute::cancel_pending(deps, env, info, id),
+        ExecuteMsg::OpenProposal {
+            proposal_id,
+            voting_end,
+        } => execute::open_proposal(deps, env, info, proposal_id, voting_end),
+        ExecuteMsg::Vote { proposal_id, vote } => execute::vote(deps, env, info, proposal_id, vote),
+        ExecuteMsg::CastVote { proposal_id } => execute::cast_vote(deps, env, proposal_id),
+        ExecuteMsg::SetDefaultVote { proposal_id, vote } => {
+            execute::set_default_vote(deps, env, info, proposal_id, vote)
+        }
+        ExecuteMsg::AddHook { addr } => execute::add_hook(deps, env, info, addr),
+        ExecuteMsg::RemoveHook { addr } => execute::remove_hook(deps, env, info, addr),
+        ExecuteMsg::SetRewardMode { mode } => execute::set_reward_mode(deps, info, mode),
+        ExecuteMsg::ClaimRewards {} => execute::claim_rewards(deps, info),
+        ExecuteMsg::SetYieldRecipient {
+            recipient,
+            ratio,
+            payout,
+        } => execute::set_yield_recipient(deps, info, recipient, ratio, payout),
+        ExecuteMsg::ClearYieldRecipient {} => execute::clear_yield_recipient(deps, info),
     }
 }
 
 mod execute {
 
+    use cosmwasm_std::{GovMsg, Storage, VoteOption, WeightedVoteOption};
     use cw_utils::Expiration;
 
-    use crate::state::{ALLOWED_ADDRESSES, PENDING_CLAIMS, VALIDATOR_LIST};
+    use crate::state::{
+        AllowedAddress, ContractStatus, GovProposal, Lock, PendingChange, PendingProposal,
+        RebondDetails, Referral, RewardMode, YieldEntry, YieldRecipient, ALLOWED_ADDRESSES,
+        COMMISSION_EARNINGS, DEFAULT_VOTES, GOV_PROPOSALS, HOOKS, LIQUID_REWARDS, LOCKS,
+        LOCK_COUNT, PENALTY_POOL, PENDING_CLAIMS, PENDING_PROPOSALS, PENDING_REBONDS,
+        PROPOSAL_COUNT, REFERRALS, REFERRER_STATS, REWARD_MODES, STATUS, TREASURY_COMMISSION,
+        USER_EARNINGS, VALIDATOR_LIST, VOTES, WIND_DOWN_SHARES, YIELD_HISTORY, YIELD_RECIPIENTS,
+        YOUNG_DEPOSITS,
+    };
 
     use super::{
         utils::{
-            check_unbonding_cooldown, compute_redelegate_msgs, delegate_msgs_for_validators,
-            distribute_msgs_for_validators, unwrap_stake_details,
+            add_unbonding_entry, check_address_cap, check_deposit_limits, check_unbonding_cooldown,
+            commission_baseline, compute_rebalance_msgs, compute_redelegate_msgs, credit_stake,
+            delegate_msgs_for_validators, distribute_msgs_for_validators, early_exit_fee,
+            ensure_active, ensure_unlocked, load_allowed_address, load_open_proposal, lock_boost,
+            log_admin_action, log_executed_action, max_drift, next_restake_id,
+            record_commission_change, remaining_validator_weights, remove_stake_details,
+            route_redelegations, save_stake_details, save_young_deposits, split_treasury,
+            stake_changed_hooks, take_young_deposits, tally_votes, to_json_string,
+            unbonding_cooldown, undelegate_msgs_for_validators, underweight_delegate_msgs,
+            unwrap_stake_details, update_stake_details, update_total, validate_commission_change,
+            validate_drift_threshold, validate_early_exit_fee, validate_lock_tiers, validate_ratio,
+            validate_treasury, validate_unlock_penalty, validate_vote_quorum,
+            validator_allocations, vote_participation, voting_power, young_deposits,
         },
         *,
     };
 
     pub fn update_config(
         deps: DepsMut,
+        env: Env,
         info: MessageInfo,
-        new_owner: Option<String>,
-        treasury: Option<String>,
-        new_restake_commission: Option<Decimal>,
-        new_transfer_commission: Option<Decimal>,
-        new_unbonding_period: Option<u64>,
+        update: ConfigUpdate,
     ) -> Result<Response, ContractError> {
-        let mut config = CONFIG.load(deps.storage)?;
+        let config = CONFIG.load(deps.storage)?;
         if config.owner != info.sender {
             return Err(ContractError::Unauthorized {});
         }
+        if timelock_active(&config) {
+            return Err(ContractError::TimelockActive {});
+        }
+
+        save_config_update(deps, &env, &info.sender, None, config, update)?;
+        Ok(Response::new().add_attribute("action", "config_updated"))
+    }
+
+    fn save_config_update(
+        deps: DepsMut,
+        env: &Env,
+        sender: &Addr,
+        executor: Option<&Addr>,
+        old_config: Config,
+        update: ConfigUpdate,
+    ) -> Result<(), ContractError> {
+        let config = apply_config_update(deps.as_ref(), env, old_config.clone(), update)?;
+        record_commission_change(
+            deps.storage,
+            env,
+            "restake",
+            old_config.restake_commission,
+            config.restake_commission,
+        )?;
+        record_commission_change(
+            deps.storage,
+            env,
+            "transfer",
+            old_config.transfer_commission,
+            config.transfer_commission,
+        )?;
+        CONFIG.save(deps.storage, &config)?;
+        log_executed_action(
+            deps.storage,
+            env,
+            sender,
+            executor,
+            "update_config",
+            Some(to_json_string(&old_config)?),
+            Some(to_json_string(&config)?),
+        )?;
+        Ok(())
+    }
+
+    fn timelock_active(config: &Config) -> bool {
+        config.timelock_delay != Duration::Time(0)
+    }
+
+    fn apply_config_update(
+        deps: Deps,
+        env: &Env,
+        mut config: Config,
+        update: ConfigUpdate,
+    ) -> Result<Config, ContractError> {
+        let ConfigUpdate {
+            owner: new_owner,
+            treasury,
+            restake_commission: new_restake_commission,
+            transfer_commission: new_transfer_commission,
+            unbonding_period: new_unbonding_period,
+            max_entries: new_max_entries,
+            drift_threshold: new_drift_threshold,
+            referral_commission: new_referral_commission,
+            referral_period: new_referral_period,
+            restake_commission_payout: new_restake_commission_payout,
+            transfer_commission_payout: new_transfer_commission_payout,
+            timelock_delay: new_timelock_delay,
+            vote_quorum: new_vote_quorum,
+            lock_tiers: new_lock_tiers,
+            early_unlock_penalty: new_early_unlock_penalty,
+            early_exit_fee: new_early_exit_fee,
+            early_exit_blocks: new_early_exit_blocks,
+            early_exit_treasury_share: new_early_exit_treasury_share,
+            deposit_cap: new_deposit_cap,
+            address_cap: new_address_cap,
+            min_delegation: new_min_delegation,
+            min_undelegation: new_min_undelegation,
+        } = update;
 
         if let Some(owner) = new_owner {
             let owner = deps.api.addr_validate(&owner)?;
@@ -178,28 +353,104 @@ mod execute {
         }
 
         if let Some(treasury) = treasury {
-            let treasury = deps.api.addr_validate(&treasury)?;
-            config.treasury = treasury;
+            config.treasury = validate_treasury(deps.api, treasury)?;
         }
 
+        // changes are capped against the commission at the start of the current window,
+        // so repeated updates can't move it any further
         if let Some(restake_commission) = new_restake_commission {
-            config.restake_commission = restake_commission;
+            let baseline =
+                commission_baseline(deps.storage, env, "restake", config.restake_commission)?;
+            config.restake_commission = validate_commission_change(baseline, restake_commission)?;
         }
 
         if let Some(transfer_commission) = new_transfer_commission {
-            config.transfer_commission = transfer_commission;
+            let baseline =
+                commission_baseline(deps.storage, env, "transfer", config.transfer_commission)?;
+            config.transfer_commission = validate_commission_change(baseline, transfer_commission)?;
         }
 
-        if let Some(unbonding_period) = new_unbonding_period {
-            config.unbonding_period = Duration::Time(unbonding_period);
+        if new_unbonding_period.is_some() || new_max_entries.is_some() {
+            if let Some(unbonding_period) = new_unbonding_period {
+                config.unbonding_period = Duration::Time(unbonding_period);
+            }
+            if let Some(max_entries) = new_max_entries {
+                config.max_entries = max_entries;
+            }
+            config.min_unbonding_cooldown =
+                unbonding_cooldown(config.unbonding_period, config.max_entries)?;
         }
 
-        CONFIG.save(deps.storage, &config)?;
-        Ok(Response::new().add_attribute("action", "config_updated"))
+        if let Some(drift_threshold) = new_drift_threshold {
+            config.drift_threshold = validate_drift_threshold(drift_threshold)?;
+        }
+
+        if let Some(referral_commission) = new_referral_commission {
+            config.referral_commission = validate_ratio(referral_commission)?;
+        }
+
+        if let Some(referral_period) = new_referral_period {
+            config.referral_period = Duration::Time(referral_period);
+        }
+
+        if let Some(payout) = new_restake_commission_payout {
+            config.restake_commission_payout = payout;
+        }
+
+        if let Some(payout) = new_transfer_commission_payout {
+            config.transfer_commission_payout = payout;
+        }
+
+        if let Some(timelock_delay) = new_timelock_delay {
+            config.timelock_delay = Duration::Time(timelock_delay);
+        }
+
+        if let Some(vote_quorum) = new_vote_quorum {
+            config.vote_quorum = validate_vote_quorum(vote_quorum)?;
+        }
+
+        if let Some(lock_tiers) = new_lock_tiers {
+            config.lock_tiers = validate_lock_tiers(lock_tiers)?;
+        }
+
+        if let Some(penalty) = new_early_unlock_penalty {
+            config.early_unlock_penalty = validate_unlock_penalty(penalty)?;
+        }
+
+        if let Some(fee) = new_early_exit_fee {
+            config.early_exit_fee = validate_early_exit_fee(fee)?;
+        }
+
+        if let Some(blocks) = new_early_exit_blocks {
+            config.early_exit_blocks = blocks;
+        }
+
+        if let Some(share) = new_early_exit_treasury_share {
+            config.early_exit_treasury_share = validate_ratio(share)?;
+        }
+
+        if let Some(cap) = new_deposit_cap {
+            config.deposit_cap = cap;
+        }
+
+        if let Some(cap) = new_address_cap {
+            config.address_cap = cap;
+        }
+
+        if let Some(min) = new_min_delegation {
+            config.min_delegation = min;
+        }
+
+        if let Some(min) = new_min_undelegation {
+            config.min_undelegation = min;
+        }
+
+        Ok(config)
     }
 
     pub fn update_validator_list(
         deps: DepsMut,
+        env: Env,
         info: MessageInfo,
         new_validator_list: Vec<(String, Decimal)>,
     ) -> Result<Response, ContractError> {
@@ -207,8 +458,37 @@ mod execute {
         if info.sender != config.owner {
             return Err(ContractError::Unauthorized {});
         }
+        if timelock_active(&config) {
+            return Err(ContractError::TimelockActive {});
+        }
 
-        let mut sum = Decimal::zero();
+        let redelegate_msgs =
+            apply_validator_list(deps, &env, &info.sender, None, &config, new_validator_list)?;
+
+        Ok(Response::new()
+            .add_messages(redelegate_msgs)
+            .add_attribute("action", "validator_list_updated"))
+    }
+
+    fn validate_validator_list(validator_list: &[(String, Decimal)]) -> Result<(), ContractError> {
+        let sum = validator_list
+            .iter()
+            .fold(Decimal::zero(), |sum, (_, weight)| sum + weight);
+        if sum != Decimal::one() {
+            return Err(ContractError::InvalidValidatorList {});
+        }
+        Ok(())
+    }
+
+    fn apply_validator_list(
+        deps: DepsMut,
+        env: &Env,
+        sender: &Addr,
+        executor: Option<&Addr>,
+        config: &Config,
+        new_validator_list: Vec<(String, Decimal)>,
+    ) -> Result<Vec<StakingMsg>, ContractError> {
+        validate_validator_list(&new_validator_list)?;
 
         let old_validator_list = VALIDATOR_LIST
             .range(deps.storage, None, None, Ascending)
@@ -220,143 +500,826 @@ mod execute {
         let redelegate_msgs = compute_redelegate_msgs(
             total_staked.amount,
             &config.denom,
-            old_validator_list,
+            old_validator_list.clone(),
             new_validator_list.clone(),
         )?;
 
+        log_executed_action(
+            deps.storage,
+            env,
+            sender,
+            executor,
+            "update_validator_list",
+            Some(to_json_string(&old_validator_list)?),
+            Some(to_json_string(&new_validator_list)?),
+        )?;
+
         VALIDATOR_LIST.clear(deps.storage);
         for (validator, weight) in new_validator_list {
-            sum += weight;
             VALIDATOR_LIST.save(deps.storage, validator, &weight)?;
         }
 
-        if sum != Decimal::one() {
-            return Err(ContractError::InvalidValidatorList {});
-        }
+        route_redelegations(deps, env, config, redelegate_msgs)
+    }
+
+    fn save_proposal(
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        config: &Config,
+        change: PendingChange,
+    ) -> Result<Response, ContractError> {
+        let id = PROPOSAL_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+        PROPOSAL_COUNT.save(storage, &id)?;
+
+        let execute_after = config.timelock_delay.after(&env.block);
+        let proposal = PendingProposal {
+            proposer: sender.clone(),
+            change,
+            execute_after,
+        };
+        PENDING_PROPOSALS.save(storage, id, &proposal)?;
+        log_admin_action(
+            storage,
+            env,
+            sender,
+            "propose_change",
+            None,
+            Some(to_json_string(&(id, proposal))?),
+        )?;
 
         Ok(Response::new()
-            .add_messages(redelegate_msgs)
-            .add_attribute("action", "validator_list_updated"))
+            .add_attribute("action", "change_proposed")
+            .add_attribute("proposal_id", id.to_string())
+            .add_attribute("execute_after", execute_after.to_string()))
     }
 
-    pub fn delegate(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
-        let denom = CONFIG.load(deps.as_ref().storage)?.denom;
-        if info.funds.len() != 1 {
-            return Err(ContractError::NoFunds {});
+    pub fn propose_config_change(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        update: ConfigUpdate,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
         }
 
-        let amount = info.funds[0].clone();
-
-        let msgs = delegate_msgs_for_validators(deps.as_ref(), amount.clone(), true)?;
+        // reject invalid updates upfront; they are validated again on execution
+        apply_config_update(deps.as_ref(), &env, config.clone(), update.clone())?;
 
-        let stake = Stake {
-            amount: amount.clone(),
-            join_height: env.block.height,
-        };
-        STAKE_DETAILS.update(
+        save_proposal(
             deps.storage,
+            &env,
             &info.sender,
-            |stake_details| -> StdResult<_> {
-                let mut stake_details =
-                    unwrap_stake_details(stake_details, denom, env.block.height);
-                stake_details.partials.push(stake);
-                Ok(stake_details)
-            },
-        )?;
+            &config,
+            PendingChange::Config(update),
+        )
+    }
 
-        TOTAL.update(deps.storage, |total| -> StdResult<_> {
-            Ok(coin((total.amount + amount.amount).u128(), total.denom))
-        })?;
+    pub fn propose_validator_list(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        new_validator_list: Vec<(String, Decimal)>,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
 
-        Ok(Response::new()
-            .add_attribute("action", "delegate")
-            .add_attribute("sender", info.sender.to_string())
-            .add_attribute("amount", amount.to_string())
-            .add_messages(msgs))
+        validate_validator_list(&new_validator_list)?;
+
+        save_proposal(
+            deps.storage,
+            &env,
+            &info.sender,
+            &config,
+            PendingChange::ValidatorList(new_validator_list),
+        )
     }
 
-    pub fn queue_undelegate(
+    pub fn execute_pending(
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
-        amount: Coin,
+        id: u64,
     ) -> Result<Response, ContractError> {
-        let mut stake_details = STAKE_DETAILS
-            .load(deps.storage, &info.sender)
-            .map_err(|_| ContractError::DelegationNotFound {})?;
-
-        // TODO: Check if the total amount is equal to zero -> remove entry from memory
-        stake_details.consolidate_partials(deps.storage)?;
-        stake_details.total.amount = stake_details
-            .total
-            .amount
-            .checked_sub(amount.amount)
-            .map_err(|_| ContractError::NotEnoughToUndelegate {
-                wanted: amount.amount,
-                have: stake_details.total.amount,
-            })?;
+        let proposal = PENDING_PROPOSALS
+            .may_load(deps.storage, id)?
+            .ok_or(ContractError::ProposalNotFound { id })?;
+        if !proposal.execute_after.is_expired(&env.block) {
+            return Err(ContractError::TimelockNotExpired {
+                execute_after: proposal.execute_after,
+            });
+        }
+        PENDING_PROPOSALS.remove(deps.storage, id);
 
-        STAKE_DETAILS.save(deps.storage, &info.sender, &stake_details)?;
+        let config = CONFIG.load(deps.storage)?;
+        let response = Response::new()
+            .add_attribute("action", "pending_executed")
+            .add_attribute("proposal_id", id.to_string());
+
+        // the change is logged as the proposer's, with the caller recorded as its executor
+        let proposer = &proposal.proposer;
+        let executor = Some(&info.sender);
+        match proposal.change {
+            PendingChange::Config(update) => {
+                save_config_update(deps, &env, proposer, executor, config, update)?;
+                Ok(response)
+            }
+            PendingChange::ValidatorList(new_validator_list) => {
+                let redelegate_msgs = apply_validator_list(
+                    deps,
+                    &env,
+                    proposer,
+                    executor,
+                    &config,
+                    new_validator_list,
+                )?;
+                Ok(response.add_messages(redelegate_msgs))
+            }
+        }
+    }
 
-        // IMPORTANT: This will only queue the undelegation.
-        // Create (or update) a pending claim to later be able to get tokens back.
-        PENDING_CLAIMS.update(deps.storage, &info.sender, |claim| -> StdResult<_> {
-            let claim = claim.unwrap_or(Uint128::zero());
-            Ok(claim + amount.amount)
-        })?;
+    pub fn cancel_pending(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        let proposal = PENDING_PROPOSALS
+            .may_load(deps.storage, id)?
+            .ok_or(ContractError::ProposalNotFound { id })?;
+        PENDING_PROPOSALS.remove(deps.storage, id);
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "cancel_pending",
+            Some(to_json_string(&(id, proposal))?),
+            None,
+        )?;
 
         Ok(Response::new()
-            .add_attribute("action", "queue_undelegate")
-            .add_attribute("sender", info.sender.to_string())
-            .add_attribute("amount", amount.to_string()))
+            .add_attribute("action", "pending_cancelled")
+            .add_attribute("proposal_id", id.to_string()))
     }
 
-    pub fn batch_unbond(
+    pub fn open_proposal(
         deps: DepsMut,
         env: Env,
-        _info: MessageInfo,
+        info: MessageInfo,
+        proposal_id: u64,
+        voting_end: Timestamp,
     ) -> Result<Response, ContractError> {
         let config = CONFIG.load(deps.storage)?;
-        check_unbonding_cooldown(&deps, &config, &env)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if GOV_PROPOSALS.has(deps.storage, proposal_id) {
+            return Err(ContractError::ProposalAlreadyOpen { proposal_id });
+        }
 
-        LATEST_UNBONDING.save(deps.storage, &Expiration::AtTime(env.block.time))?;
+        let proposal = GovProposal {
+            height: env.block.height,
+            voting_end,
+        };
+        GOV_PROPOSALS.save(deps.storage, proposal_id, &proposal)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "open_proposal",
+            None,
+            Some(to_json_string(&(proposal_id, &proposal))?),
+        )?;
 
-        let release_timestamp = config.unbonding_period.after(&env.block);
-        let pending_claims = PENDING_CLAIMS
-            .range(deps.storage, None, None, Order::Ascending)
-            .collect::<StdResult<Vec<(Addr, Uint128)>>>()?;
+        Ok(Response::new()
+            .add_attribute("action", "open_proposal")
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("voting_end", voting_end.to_string()))
+    }
 
-        if pending_claims.is_empty() {}
+    pub fn vote(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        proposal_id: u64,
+        vote: VoteOption,
+    ) -> Result<Response, ContractError> {
+        let proposal = load_open_proposal(deps.storage, &env, proposal_id)?;
+        if voting_power(deps.storage, &info.sender, proposal.height)?.is_zero() {
+            return Err(ContractError::NoVotingPower {});
+        }
+        VOTES.save(deps.storage, (proposal_id, &info.sender), &vote)?;
 
-        let mut unbond_amount = Uint128::zero();
-        pending_claims.iter().for_each(|(addr, amount)| {
-            unbond_amount += amount;
-            let claim_details = ClaimDetails {
-                release_timestamp,
-                amount: coin(amount.u128(), &config.denom),
-            };
+        Ok(Response::new()
+            .add_attribute("action", "vote")
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("voter", info.sender)
+            .add_attribute("vote", format!("{vote:?}")))
+    }
 
-            PENDING_CLAIMS.remove(deps.storage, addr);
+    /// Votes are weighted by the stake at the height the proposal was opened, so stake moved
+    /// after voting doesn't count twice
+    pub fn cast_vote(deps: DepsMut, env: Env, proposal_id: u64) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        let proposal = load_open_proposal(deps.storage, &env, proposal_id)?;
 
-            UNBONDING_CLAIMS
-                .update(deps.storage, addr, |vec_claims| -> StdResult<_> {
-                    let mut vec_claims = vec_claims.unwrap_or_default();
-                    vec_claims.push(claim_details);
-                    Ok(vec_claims)
+        let tally = tally_votes(deps.storage, proposal_id, proposal.height)?;
+        let voted: Uint128 = tally.iter().map(|(_, stake)| stake).sum();
+        let participation = vote_participation(deps.storage, voted, proposal.height)?;
+
+        let options = if voted.is_zero() || participation < config.vote_quorum {
+            let vote = DEFAULT_VOTES.may_load(deps.storage, proposal_id)?.ok_or(
+                ContractError::QuorumNotReached {
+                    participation,
+                    quorum: config.vote_quorum,
+                },
+            )?;
+            vec![WeightedVoteOption {
+                option: vote,
+                weight: Decimal::one(),
+            }]
+        } else {
+            let mut options: Vec<WeightedVoteOption> = tally
+                .into_iter()
+                .filter(|(_, stake)| !stake.is_zero())
+                .map(|(option, stake)| WeightedVoteOption {
+                    option,
+                    weight: Decimal::from_ratio(stake, voted),
                 })
-                .unwrap();
-        });
+                .collect();
+            // weights have to sum up to exactly 1, so the last one takes the rounding error
+            let (last, rest) = options.split_last_mut().unwrap();
+            last.weight = Decimal::one()
+                - rest
+                    .iter()
+                    .fold(Decimal::zero(), |sum, option| sum + option.weight);
+            options
+        };
 
-        // hypothesis: The initial pending claim is not being removed, so once we call the second batch_unbond, It reaches this point, which it shouldnt. It should error or exit before here.
-        // issue is here: See backtrace :9
-        TOTAL.update(deps.storage, |total| -> StdResult<_> {
-            dbg!(Ok(coin((total.amount - unbond_amount).u128(), total.denom)))
-        })?;
+        Ok(Response::new()
+            .add_attribute("action", "cast_vote")
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("participation", participation.to_string())
+            .add_message(GovMsg::VoteWeighted {
+                proposal_id,
+                options,
+            }))
+    }
+
+    pub fn set_default_vote(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        proposal_id: u64,
+        vote: VoteOption,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let old_vote = DEFAULT_VOTES.may_load(deps.storage, proposal_id)?;
+        DEFAULT_VOTES.save(deps.storage, proposal_id, &vote)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "set_default_vote",
+            old_vote
+                .map(|old| to_json_string(&(proposal_id, old)))
+                .transpose()?,
+            Some(to_json_string(&(proposal_id, &vote))?),
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "set_default_vote")
+            .add_attribute("proposal_id", proposal_id.to_string())
+            .add_attribute("vote", format!("{vote:?}")))
+    }
+
+    pub fn add_hook(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        addr: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let hook = deps.api.addr_validate(&addr)?;
+        let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+        if hooks.contains(&hook) {
+            return Err(ContractError::HookAlreadyRegistered { addr });
+        }
+        hooks.push(hook);
+        HOOKS.save(deps.storage, &hooks)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "add_hook",
+            None,
+            Some(addr.clone()),
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "add_hook")
+            .add_attribute("hook", addr))
+    }
+
+    pub fn remove_hook(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        addr: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let hook = deps.api.addr_validate(&addr)?;
+        let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+        if !hooks.contains(&hook) {
+            return Err(ContractError::HookNotRegistered { addr });
+        }
+        hooks.retain(|registered| *registered != hook);
+        HOOKS.save(deps.storage, &hooks)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "remove_hook",
+            Some(addr.clone()),
+            None,
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "remove_hook")
+            .add_attribute("hook", addr))
+    }
+
+    pub fn set_reward_mode(
+        deps: DepsMut,
+        info: MessageInfo,
+        mode: RewardMode,
+    ) -> Result<Response, ContractError> {
+        match mode {
+            RewardMode::Compound => REWARD_MODES.remove(deps.storage, &info.sender),
+            RewardMode::Liquid => REWARD_MODES.save(deps.storage, &info.sender, &mode)?,
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "set_reward_mode")
+            .add_attribute("sender", info.sender)
+            .add_attribute("mode", format!("{mode:?}")))
+    }
+
+    pub fn claim_rewards(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+        let amount = LIQUID_REWARDS
+            .may_load(deps.storage, &info.sender)?
+            .unwrap_or_default();
+        if amount.is_zero() {
+            return Err(ContractError::NoLiquidRewards {});
+        }
+        LIQUID_REWARDS.remove(deps.storage, &info.sender);
+
+        let config = CONFIG.load(deps.storage)?;
+        Ok(Response::new()
+            .add_attribute("action", "claim_rewards")
+            .add_attribute("sender", info.sender.to_string())
+            .add_attribute("amount", amount)
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: vec![coin(amount.u128(), config.denom)],
+            }))
+    }
+
+    pub fn set_yield_recipient(
+        deps: DepsMut,
+        info: MessageInfo,
+        recipient: String,
+        ratio: Decimal,
+        payout: Option<PayoutMode>,
+    ) -> Result<Response, ContractError> {
+        let recipient = deps.api.addr_validate(&recipient)?;
+        if recipient == info.sender {
+            return Err(ContractError::SelfYieldRecipient {});
+        }
+        if ratio.is_zero() || ratio > Decimal::one() {
+            return Err(ContractError::InvalidYieldRatio { ratio });
+        }
+
+        YIELD_RECIPIENTS.save(
+            deps.storage,
+            &info.sender,
+            &YieldRecipient {
+                recipient: recipient.clone(),
+                ratio,
+                payout: payout.unwrap_or(PayoutMode::Stake),
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("action", "set_yield_recipient")
+            .add_attribute("sender", info.sender)
+            .add_attribute("recipient", recipient)
+            .add_attribute("ratio", ratio.to_string()))
+    }
+
+    pub fn clear_yield_recipient(
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        YIELD_RECIPIENTS.remove(deps.storage, &info.sender);
+
+        Ok(Response::new()
+            .add_attribute("action", "clear_yield_recipient")
+            .add_attribute("sender", info.sender))
+    }
+
+    pub fn delegate(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        referrer: Option<String>,
+    ) -> Result<Response, ContractError> {
+        ensure_active(deps.storage)?;
+        let config = CONFIG.load(deps.as_ref().storage)?;
+        let denom = config.denom.clone();
+        if info.funds.len() != 1 {
+            return Err(ContractError::NoFunds {});
+        }
+
+        let amount = info.funds[0].clone();
+        check_deposit_limits(deps.storage, &config, &info.sender, amount.amount)?;
+
+        let mut response = Response::new();
+        if let Some(referrer) = referrer {
+            let referrer = deps.api.addr_validate(&referrer)?;
+            if referrer == info.sender {
+                return Err(ContractError::SelfReferral {});
+            }
+            load_allowed_address(deps.storage, &env, &referrer)?;
+
+            // the first referrer keeps the user until its referral period is over
+            let active_referral = REFERRALS
+                .may_load(deps.storage, &info.sender)?
+                .filter(|referral| !referral.expires.is_expired(&env.block));
+            if active_referral.is_none() {
+                REFERRALS.save(
+                    deps.storage,
+                    &info.sender,
+                    &Referral {
+                        referrer: referrer.clone(),
+                        expires: config.referral_period.after(&env.block),
+                    },
+                )?;
+                REFERRER_STATS.update(deps.storage, &referrer, |stats| -> StdResult<_> {
+                    let mut stats = stats.unwrap_or_default();
+                    stats.referred_users += 1;
+                    Ok(stats)
+                })?;
+                response = response.add_attribute("referrer", referrer);
+            }
+        }
+
+        let msgs = delegate_msgs_for_validators(deps.as_ref(), amount.clone(), true)?;
+
+        let stake = Stake {
+            amount: amount.clone(),
+            join_height: env.block.height,
+        };
+        let change = update_stake_details(
+            deps.storage,
+            env.block.height,
+            &info.sender,
+            |stake_details| -> StdResult<_> {
+                let mut stake_details =
+                    unwrap_stake_details(stake_details, denom, env.block.height);
+                stake_details.partials.push(stake);
+                Ok(stake_details)
+            },
+        )?;
+        let mut deposits = young_deposits(deps.storage, &config, &env, &info.sender)?;
+        deposits.push(Stake {
+            amount: amount.clone(),
+            join_height: env.block.height,
+        });
+        save_young_deposits(deps.storage, &config, &env, &info.sender, deposits)?;
+        let hook_msgs = stake_changed_hooks(deps.storage, vec![change])?;
+
+        update_total(deps.storage, env.block.height, |total| -> StdResult<_> {
+            Ok(coin((total.amount + amount.amount).u128(), total.denom))
+        })?;
+
+        Ok(response
+            .add_attribute("action", "delegate")
+            .add_attribute("sender", info.sender.to_string())
+            .add_attribute("amount", amount.to_string())
+            .add_messages(msgs)
+            .add_submessages(hook_msgs))
+    }
+
+    pub fn delegate_locked(
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        duration: u64,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        let tier = config
+            .lock_tiers
+            .iter()
+            .find(|tier| tier.duration == duration)
+            .ok_or(ContractError::InvalidLockDuration { duration })?;
+
+        let response = delegate(deps.branch(), env.clone(), info.clone(), None)?;
+        let amount = info.funds[0].amount;
+
+        let id = LOCK_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+        LOCK_COUNT.save(deps.storage, &id)?;
+        let unlocks_at = env.block.time.plus_seconds(duration);
+        LOCKS.save(
+            deps.storage,
+            (&info.sender, id),
+            &Lock {
+                amount,
+                multiplier: tier.multiplier,
+                start_height: env.block.height,
+                unlocks_at,
+            },
+        )?;
+
+        Ok(response
+            .add_attribute("lock_id", id.to_string())
+            .add_attribute("unlocks_at", unlocks_at.to_string()))
+    }
+
+    pub fn unlock(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        id: u64,
+    ) -> Result<Response, ContractError> {
+        let lock = LOCKS
+            .may_load(deps.storage, (&info.sender, id))?
+            .ok_or(ContractError::LockNotFound { id })?;
+        LOCKS.remove(deps.storage, (&info.sender, id));
+
+        // nothing is forfeited once the lock expired or everything was undelegated
+        let config = CONFIG.load(deps.storage)?;
+        let active = STATUS.may_load(deps.storage)?.unwrap_or_default() == ContractStatus::Active;
+        let penalty = if active && env.block.time < lock.unlocks_at {
+            config.early_unlock_penalty * lock.amount
+        } else {
+            Uint128::zero()
+        };
+
+        let mut changes = vec![];
+        if !penalty.is_zero() {
+            let mut stake_details = STAKE_DETAILS
+                .load(deps.storage, &info.sender)
+                .map_err(|_| ContractError::DelegationNotFound {})?;
+            stake_details.consolidate_partials(deps.storage)?;
+            // a lock made since the last payment is still a partial stake
+            let partial = stake_details.partials.iter_mut().find(|stake| {
+                stake.join_height == lock.start_height && stake.amount.amount >= penalty
+            });
+            match partial {
+                Some(stake) => stake.amount.amount -= penalty,
+                None => {
+                    stake_details.total.amount = stake_details
+                        .total
+                        .amount
+                        .checked_sub(penalty)
+                        .map_err(|_| ContractError::NotEnoughToUndelegate {
+                            wanted: penalty,
+                            have: stake_details.total.amount,
+                        })?
+                }
+            }
+            stake_details
+                .partials
+                .retain(|stake| !stake.amount.amount.is_zero());
+            changes.push(save_stake_details(
+                deps.storage,
+                env.block.height,
+                &info.sender,
+                &stake_details,
+            )?);
+
+            // the penalty stays delegated, so TOTAL doesn't change
+            for (recipient, amount) in split_treasury(&config.treasury, penalty) {
+                changes.push(credit_stake(
+                    deps.storage,
+                    &config,
+                    &env,
+                    &recipient,
+                    amount,
+                )?);
+            }
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "unlock")
+            .add_attribute("sender", info.sender.to_string())
+            .add_attribute("lock_id", id.to_string())
+            .add_attribute("penalty", penalty)
+            .add_submessages(stake_changed_hooks(deps.storage, changes)?))
+    }
+
+    pub fn queue_undelegate(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        amount: Coin,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        let stake_details = STAKE_DETAILS.may_load(deps.storage, &info.sender)?;
+        // positions below the minimum can still leave in full
+        let full_exit = stake_details.as_ref().map_or(false, |stake_details| {
+            stake_details.staked() == amount.amount
+        });
+        if amount.amount < config.min_undelegation && !full_exit {
+            return Err(ContractError::UndelegationTooSmall {
+                min: config.min_undelegation,
+                amount: amount.amount,
+            });
+        }
+        // older stake leaves first, only deposits still in the fee period pay it
+        let staked = stake_details
+            .map(|stake_details| stake_details.staked())
+            .unwrap_or_default();
+        let mut deposits = young_deposits(deps.storage, &config, &env, &info.sender)?;
+        let taken = take_young_deposits(&mut deposits, staked, amount.amount);
+        save_young_deposits(deps.storage, &config, &env, &info.sender, deposits)?;
+        let fee = early_exit_fee(&config, &taken);
+        let mut changes = vec![queue_unbonding(
+            deps.storage,
+            &env,
+            &info.sender,
+            amount.amount,
+            fee,
+        )?];
+
+        if !fee.is_zero() {
+            // Fee stays delegated: the treasury part is credited as stake right away, the rest
+            // is shared between stakers at the next restake
+            let treasury_fee = config.early_exit_treasury_share * fee;
+            for (recipient, share) in split_treasury(&config.treasury, treasury_fee) {
+                changes.push(credit_stake(
+                    deps.storage,
+                    &config,
+                    &env,
+                    &recipient,
+                    share,
+                )?);
+            }
+            let pool_fee = fee - treasury_fee;
+            if !pool_fee.is_zero() {
+                let pool = PENALTY_POOL.may_load(deps.storage)?.unwrap_or_default();
+                PENALTY_POOL.save(deps.storage, &(pool + pool_fee))?;
+                update_total(deps.storage, env.block.height, |total| -> StdResult<_> {
+                    Ok(coin((total.amount - pool_fee).u128(), total.denom))
+                })?;
+            }
+        }
+        let hook_msgs = stake_changed_hooks(deps.storage, changes)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "queue_undelegate")
+            .add_attribute("sender", info.sender.to_string())
+            .add_attribute("amount", amount.to_string())
+            .add_attribute("early_exit_fee", fee)
+            .add_submessages(hook_msgs))
+    }
+
+    /// Moves stake commission of a treasury recipient to its pending claim, so it gets
+    /// unbonded with the next batch
+    pub fn withdraw_commission(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        amount: Uint128,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if !config
+            .treasury
+            .iter()
+            .any(|(recipient, _)| *recipient == info.sender)
+        {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let change = queue_unbonding(deps.storage, &env, &info.sender, amount, Uint128::zero())?;
+        let hook_msgs = stake_changed_hooks(deps.storage, vec![change])?;
+
+        Ok(Response::new()
+            .add_attribute("action", "withdraw_commission")
+            .add_attribute("sender", info.sender.to_string())
+            .add_attribute("amount", amount)
+            .add_submessages(hook_msgs))
+    }
+
+    /// Removes `amount` from the stake of `address` and queues it for unbonding, less `fee`
+    fn queue_unbonding(
+        storage: &mut dyn Storage,
+        env: &Env,
+        address: &Addr,
+        amount: Uint128,
+        fee: Uint128,
+    ) -> Result<StakeChange, ContractError> {
+        let mut stake_details = STAKE_DETAILS
+            .load(storage, address)
+            .map_err(|_| ContractError::DelegationNotFound {})?;
+
+        // TODO: Check if the total amount is equal to zero -> remove entry from memory
+        stake_details.consolidate_partials(storage)?;
+        // a full exit takes partial stakes along, they won't earn anything anymore
+        if amount == stake_details.staked() {
+            stake_details.total.amount = amount;
+            stake_details.partials.clear();
+        }
+        stake_details.total.amount =
+            stake_details
+                .total
+                .amount
+                .checked_sub(amount)
+                .map_err(|_| ContractError::NotEnoughToUndelegate {
+                    wanted: amount,
+                    have: stake_details.total.amount,
+                })?;
+
+        ensure_unlocked(storage, env, address, stake_details.staked())?;
+        let change = save_stake_details(storage, env.block.height, address, &stake_details)?;
+
+        // IMPORTANT: This will only queue the undelegation.
+        // Create (or update) a pending claim to later be able to get tokens back.
+        PENDING_CLAIMS.update(storage, address, |claim| -> StdResult<_> {
+            let claim = claim.unwrap_or(Uint128::zero());
+            Ok(claim + amount - fee)
+        })?;
+        Ok(change)
+    }
+
+    pub fn batch_unbond(
+        mut deps: DepsMut,
+        env: Env,
+        _info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        check_unbonding_cooldown(&deps, &config, &env)?;
+
+        LATEST_UNBONDING.save(deps.storage, &Expiration::AtTime(env.block.time))?;
+
+        let release_timestamp = config.unbonding_period.after(&env.block);
+        let pending_claims = PENDING_CLAIMS
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<(Addr, Uint128)>>>()?;
+
+        if pending_claims.is_empty() {}
+
+        let mut unbond_amount = Uint128::zero();
+        pending_claims.iter().for_each(|(addr, amount)| {
+            unbond_amount += amount;
+            let claim_details = ClaimDetails {
+                release_timestamp,
+                amount: coin(amount.u128(), &config.denom),
+            };
+
+            PENDING_CLAIMS.remove(deps.storage, addr);
+
+            UNBONDING_CLAIMS
+                .update(deps.storage, addr, |vec_claims| -> StdResult<_> {
+                    let mut vec_claims = vec_claims.unwrap_or_default();
+                    vec_claims.push(claim_details);
+                    Ok(vec_claims)
+                })
+                .unwrap();
+        });
+
+        // hypothesis: The initial pending claim is not being removed, so once we call the second batch_unbond, It reaches this point, which it shouldnt. It should error or exit before here.
+        // issue is here: See backtrace :9
+        update_total(deps.storage, env.block.height, |total| -> StdResult<_> {
+            Ok(coin((total.amount - unbond_amount).u128(), total.denom))
+        })?;
 
-        let undelegate_msgs = delegate_msgs_for_validators(
-            deps.as_ref(),
-            coin(unbond_amount.u128(), config.denom),
-            false,
+        let undelegate_msgs = undelegate_msgs_for_validators(
+            &mut deps,
+            &env,
+            &config,
+            coin(unbond_amount.u128(), &config.denom),
         )?;
 
         Ok(Response::new()
@@ -430,22 +1393,22 @@ mod execute {
         let reward = reward[0].clone();
 
         // Decrease reward of team_commission
-        let mut commission_msgs = vec![];
-        let reward = if config.restake_commission == Decimal::zero() {
-            reward
-        } else {
-            let commission_amount = config.restake_commission * reward.amount;
-
-            commission_msgs.push(BankMsg::Send {
-                to_address: config.treasury.to_string(),
-                amount: vec![coin(commission_amount.u128(), reward.denom.clone())],
-            });
-
-            coin((reward.amount - commission_amount).u128(), reward.denom)
-        };
+        let commission_amount = config.restake_commission * reward.amount;
+        let reward = coin((reward.amount - commission_amount).u128(), reward.denom);
 
         let reward_msgs = distribute_msgs_for_validators(deps.as_ref())?;
 
+        if STATUS.may_load(deps.storage)?.unwrap_or_default() != ContractStatus::Active {
+            return distribute_wind_down_rewards(
+                deps,
+                env,
+                &config,
+                reward.amount,
+                commission_amount,
+                reward_msgs,
+            );
+        }
+
         let last_payment_block = LAST_PAYMENT_BLOCK.load(deps.storage)?;
 
         // Map of each total stake with weight 1.0 and partial stakes with appropriate weights
@@ -479,40 +1442,304 @@ mod execute {
             });
         });
 
+        // Locked stake weighs more, which comes out of everyone else's share
+        for (addr, stake_detail) in stakes.iter() {
+            let boost = lock_boost(
+                deps.storage,
+                &env,
+                addr,
+                last_payment_block,
+                stake_detail.total.amount,
+            )?;
+            if !boost.is_zero() {
+                *addr_and_weight.entry(addr.clone()).or_default() += boost;
+                sum_of_weights += boost;
+            }
+        }
+
+        // Referrers get a slice of the commission paid on the stake of users they referred,
+        // the rest goes to the treasury
+        let mut referral_payouts: BTreeMap<Addr, Uint128> = BTreeMap::new();
+        if !commission_amount.is_zero() && !config.referral_commission.is_zero() {
+            for (addr, weight) in addr_and_weight.iter() {
+                let referral = match REFERRALS.may_load(deps.storage, addr)? {
+                    Some(referral) if !referral.expires.is_expired(&env.block) => referral,
+                    _ => continue,
+                };
+                let user_commission = *weight / sum_of_weights * commission_amount;
+                *referral_payouts.entry(referral.referrer).or_default() +=
+                    config.referral_commission * user_commission;
+            }
+        }
+
+        let mut treasury_amount = commission_amount;
+        let mut referral_commission = vec![];
+        for (referrer, payout) in referral_payouts {
+            if payout.is_zero() {
+                continue;
+            }
+            treasury_amount -= payout;
+            REFERRER_STATS.update(deps.storage, &referrer, |stats| -> StdResult<_> {
+                let mut stats = stats.unwrap_or_default();
+                stats.commission_earned += payout;
+                Ok(stats)
+            })?;
+            referral_commission.push((referrer, payout));
+        }
+        let mut commission_payouts = vec![];
+        if !treasury_amount.is_zero() {
+            let mut treasury_commission = TREASURY_COMMISSION
+                .may_load(deps.storage)?
+                .unwrap_or_default();
+            treasury_commission.restakes += treasury_amount;
+            TREASURY_COMMISSION.save(deps.storage, &treasury_commission)?;
+            commission_payouts = split_treasury(&config.treasury, treasury_amount);
+        }
+        commission_payouts.append(&mut referral_commission);
+
         // Second, iterate over those weights, calculate ratio weight/sum_of_weights and multiply that
         // by reward
+        let restake_id = next_restake_id(deps.storage)?;
         let mut sum_of_rewards = Uint128::zero();
+        // early exit fees are already delegated, they're only added to the stakes
+        let penalty_pool = PENALTY_POOL.may_load(deps.storage)?.unwrap_or_default();
+        let mut distributed_penalties = Uint128::zero();
+        // rewards of users in liquid mode stay in the contract instead of being delegated
+        let mut liquid_rewards = Uint128::zero();
+        let mut redirects = vec![];
+        let mut changes = vec![];
         addr_and_weight
             .into_iter()
             .try_for_each::<_, StdResult<()>>(|(addr, weight)| {
                 // Weight ratio of that one particular stake
                 // Knowing total sum of all weights, multiply reward by ratio.
                 let stakes_reward = weight / sum_of_weights * reward.amount; // TODO: Modify that by checking properly denom; later
-                sum_of_rewards += stakes_reward;
+                                                                             // part of the reward may go to another address
+                let stakes_reward = match YIELD_RECIPIENTS.may_load(deps.storage, &addr)? {
+                    Some(redirect) => {
+                        let redirected = redirect.ratio * stakes_reward;
+                        if !redirected.is_zero() {
+                            redirects.push((redirect, redirected));
+                        }
+                        stakes_reward - redirected
+                    }
+                    None => stakes_reward,
+                };
+                let mode = REWARD_MODES
+                    .may_load(deps.storage, &addr)?
+                    .unwrap_or_default();
+                if mode == RewardMode::Liquid {
+                    liquid_rewards += stakes_reward;
+                    LIQUID_REWARDS.update(deps.storage, &addr, |amount| -> StdResult<_> {
+                        Ok(amount.unwrap_or_default() + stakes_reward)
+                    })?;
+                } else {
+                    sum_of_rewards += stakes_reward;
+                }
+                let penalty_share = weight / sum_of_weights * penalty_pool;
+                distributed_penalties += penalty_share;
                 if let Some(stake_detail) = stakes.get_mut(&addr) {
                     stake_detail.earnings += stakes_reward;
-                    stake_detail.total.amount += stakes_reward;
-                    STAKE_DETAILS.save(deps.storage, &addr, stake_detail)?;
+                    if mode == RewardMode::Compound {
+                        stake_detail.total.amount += stakes_reward;
+                    }
+                    stake_detail.total.amount += penalty_share;
+                    // the reward actually credited, net of redirects; the stake details are
+                    // written for every staker anyway
+                    if !stakes_reward.is_zero() {
+                        USER_EARNINGS.save(deps.storage, (&addr, restake_id), &stakes_reward)?;
+                    }
+                    changes.push(save_stake_details(
+                        deps.storage,
+                        env.block.height,
+                        &addr,
+                        stake_detail,
+                    )?);
                 }
                 Ok(())
             })?;
 
-        let delegate_msgs = delegate_msgs_for_validators(deps.as_ref(), reward.clone(), true)?;
+        // Commission is paid after the rewards are distributed, so credited stake isn't
+        // overwritten; staked commission is delegated along with the reward
+        let mut commission_msgs = vec![];
+        let mut staked_commission = Uint128::zero();
+        for (recipient, amount) in commission_payouts {
+            match config.restake_commission_payout {
+                PayoutMode::Liquid => commission_msgs.push(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![coin(amount.u128(), &config.denom)],
+                }),
+                PayoutMode::Stake => {
+                    changes.push(credit_stake(
+                        deps.storage,
+                        &config,
+                        &env,
+                        &recipient,
+                        amount,
+                    )?);
+                    staked_commission += amount;
+                }
+            }
+        }
+
+        // Redirected yield is credited the same way, once the rewards are saved
+        let mut redirect_msgs = vec![];
+        let mut staked_redirects = Uint128::zero();
+        let mut liquid_redirects = Uint128::zero();
+        for (redirect, amount) in redirects {
+            match redirect.payout {
+                PayoutMode::Liquid => {
+                    redirect_msgs.push(BankMsg::Send {
+                        to_address: redirect.recipient.to_string(),
+                        amount: vec![coin(amount.u128(), &config.denom)],
+                    });
+                    liquid_redirects += amount;
+                }
+                PayoutMode::Stake => {
+                    changes.push(credit_stake(
+                        deps.storage,
+                        &config,
+                        &env,
+                        &redirect.recipient,
+                        amount,
+                    )?);
+                    staked_redirects += amount;
+                }
+            }
+        }
+
+        let delegate_amount = coin(
+            (reward.amount - liquid_rewards - liquid_redirects + staked_commission).u128(),
+            &reward.denom,
+        );
+
+        // once allocation drifted too far, use the reward to fill up underweight validators
+        let allocations = validator_allocations(deps.as_ref(), &env)?;
+        let delegate_msgs = if max_drift(&allocations) > config.drift_threshold {
+            underweight_delegate_msgs(&allocations, delegate_amount)
+        } else {
+            delegate_msgs_for_validators(deps.as_ref(), delegate_amount, true)?
+        };
+
+        // Rounding leftovers wait for the next restake
+        PENALTY_POOL.save(deps.storage, &(penalty_pool - distributed_penalties))?;
 
         // Update last payment height with current height
         LAST_PAYMENT_BLOCK.save(deps.storage, &env.block.height)?;
 
         // Update total amount of staked tokens with latest reward
-        TOTAL.update(deps.storage, |total| -> StdResult<_> {
-            Ok(coin((total.amount + sum_of_rewards).u128(), total.denom))
-        })?;
+        let total_before = TOTAL.load(deps.storage)?.amount;
+        let total_after = update_total(deps.storage, env.block.height, |total| -> StdResult<_> {
+            Ok(coin(
+                (total.amount
+                    + sum_of_rewards
+                    + staked_commission
+                    + staked_redirects
+                    + distributed_penalties)
+                    .u128(),
+                total.denom,
+            ))
+        })?
+        .amount;
+
+        YIELD_HISTORY.save(
+            deps.storage,
+            restake_id,
+            &YieldEntry {
+                height: env.block.height,
+                time: env.block.time,
+                reward: reward.amount,
+                commission: commission_amount,
+                total_before,
+                total_after,
+                blocks: env.block.height - last_payment_block,
+            },
+        )?;
 
         Ok(Response::new()
             .add_attribute("action", "restake")
             .add_attribute("amount", reward.amount)
             .add_messages(reward_msgs)
             .add_messages(commission_msgs)
-            .add_messages(delegate_msgs))
+            .add_messages(redirect_msgs)
+            .add_messages(delegate_msgs)
+            .add_submessages(stake_changed_hooks(deps.storage, changes)?))
+    }
+
+    /// While winding down, rewards are made claimable right away, split by the stake every
+    /// user had at `UndelegateAll`. The whole commission goes to the treasury as tokens.
+    fn distribute_wind_down_rewards(
+        deps: DepsMut,
+        env: Env,
+        config: &Config,
+        reward: Uint128,
+        commission_amount: Uint128,
+        reward_msgs: Vec<DistributionMsg>,
+    ) -> Result<Response, ContractError> {
+        let commission_msgs =
+            share_wind_down_rewards(deps.storage, &env, config, reward, commission_amount)?;
+
+        LAST_PAYMENT_BLOCK.save(deps.storage, &env.block.height)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "restake")
+            .add_attribute("wind_down", "true")
+            .add_attribute("amount", reward)
+            .add_messages(reward_msgs)
+            .add_messages(commission_msgs))
+    }
+
+    /// Adds claims of `reward` split by wind down shares and returns messages paying
+    /// `commission_amount` to the treasury
+    fn share_wind_down_rewards(
+        storage: &mut dyn Storage,
+        env: &Env,
+        config: &Config,
+        reward: Uint128,
+        commission_amount: Uint128,
+    ) -> StdResult<Vec<BankMsg>> {
+        let shares = WIND_DOWN_SHARES
+            .range(storage, None, None, Ascending)
+            .collect::<StdResult<Vec<(Addr, Uint128)>>>()?;
+        let total_shares: Uint128 = shares.iter().map(|(_, share)| share).sum();
+
+        // without any shares, nobody but the treasury can receive the reward
+        let (commission_amount, reward) = if total_shares.is_zero() {
+            (commission_amount + reward, Uint128::zero())
+        } else {
+            (commission_amount, reward)
+        };
+
+        let release_timestamp = Expiration::AtTime(env.block.time);
+        for (addr, share) in shares {
+            let amount = reward.multiply_ratio(share, total_shares);
+            if amount.is_zero() {
+                continue;
+            }
+            UNBONDING_CLAIMS.update(storage, &addr, |claims| -> StdResult<_> {
+                let mut claims = claims.unwrap_or_default();
+                claims.push(ClaimDetails {
+                    amount: coin(amount.u128(), &config.denom),
+                    release_timestamp,
+                });
+                Ok(claims)
+            })?;
+        }
+
+        let mut commission_msgs = vec![];
+        if !commission_amount.is_zero() {
+            let mut treasury_commission =
+                TREASURY_COMMISSION.may_load(storage)?.unwrap_or_default();
+            treasury_commission.restakes += commission_amount;
+            TREASURY_COMMISSION.save(storage, &treasury_commission)?;
+            for (recipient, amount) in split_treasury(&config.treasury, commission_amount) {
+                commission_msgs.push(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: vec![coin(amount.u128(), &config.denom)],
+                });
+            }
+        }
+        Ok(commission_msgs)
     }
 
     pub fn transfer(
@@ -523,15 +1750,25 @@ mod execute {
         amount: Uint128,
         commission_address: Option<String>,
     ) -> Result<Response, ContractError> {
+        ensure_active(deps.storage)?;
         let recipient = deps.api.addr_validate(&recipient)?;
         let config = CONFIG.load(deps.as_ref().storage)?;
 
-        STAKE_DETAILS.update(deps.storage, &sender, |stake_details| -> StdResult<_> {
-            let mut stake_details =
-                unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
-            stake_details.total.amount = stake_details.total.amount.checked_sub(amount)?;
-            Ok(stake_details)
-        })?;
+        let mut changes = vec![update_stake_details(
+            deps.storage,
+            env.block.height,
+            &sender,
+            |stake_details| -> StdResult<_> {
+                let mut stake_details =
+                    unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
+                stake_details.total.amount = stake_details.total.amount.checked_sub(amount)?;
+                Ok(stake_details)
+            },
+        )?];
+        ensure_unlocked(deps.storage, &env, &sender, changes[0].new)?;
+        let mut deposits = young_deposits(deps.storage, &config, &env, &sender)?;
+        let mut taken = take_young_deposits(&mut deposits, changes[0].old, amount);
+        save_young_deposits(deps.storage, &config, &env, &sender, deposits)?;
 
         let (amount, treasury_amount, commission_amount) = deduct_commission(
             &config,
@@ -540,15 +1777,34 @@ mod execute {
             &mut deps,
             &recipient,
             &env,
+            &mut changes,
         )?;
 
+        // young deposits keep their fee period with the recipient, for what's left of them
+        // after the commission
+        let carried = taken
+            .iter()
+            .fold(Uint128::zero(), |sum, deposit| sum + deposit.amount.amount);
+        take_young_deposits(&mut taken, carried, carried.saturating_sub(amount));
+        let mut deposits = young_deposits(deps.storage, &config, &env, &recipient)?;
+        deposits.append(&mut taken);
+        deposits.sort_by_key(|deposit| deposit.join_height);
+        save_young_deposits(deps.storage, &config, &env, &recipient, deposits)?;
+
         // add the amount to the recipient
-        STAKE_DETAILS.update(deps.storage, &recipient, |stake_details| -> StdResult<_> {
-            let mut stake_details =
-                unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
-            stake_details.total.amount = stake_details.total.amount.checked_add(amount)?;
-            Ok(stake_details)
-        })?;
+        check_address_cap(deps.storage, &config, &recipient, amount)?;
+        changes.push(update_stake_details(
+            deps.storage,
+            env.block.height,
+            &recipient,
+            |stake_details| -> StdResult<_> {
+                let mut stake_details =
+                    unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
+                stake_details.total.amount = stake_details.total.amount.checked_add(amount)?;
+                Ok(stake_details)
+            },
+        )?);
+        let hook_msgs = stake_changed_hooks(deps.storage, changes)?;
 
         Ok(Response::new()
             .add_attribute("action", "transfer")
@@ -556,12 +1812,21 @@ mod execute {
             .add_attribute("sender", &sender)
             .add_attribute("recipient", &recipient)
             .add_attribute("treasury_commission", treasury_amount)
-            .add_attribute("treasury_address", &config.treasury)
+            .add_attribute(
+                "treasury_address",
+                config
+                    .treasury
+                    .iter()
+                    .map(|(recipient, _)| recipient.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
             .add_attribute(
                 "commission_address",
                 commission_address.unwrap_or_else(|| "empty".to_string()),
             )
-            .add_attribute("commission_amount", commission_amount))
+            .add_attribute("commission_amount", commission_amount)
+            .add_submessages(hook_msgs))
     }
 
     fn deduct_commission(
@@ -571,6 +1836,7 @@ mod execute {
         deps: &mut DepsMut,
         recipient: &Addr,
         env: &Env,
+        changes: &mut Vec<StakeChange>,
     ) -> Result<(Uint128, Uint128, Uint128), ContractError> {
         let mut treasury_amount = Uint128::zero();
         let mut commission_amount = Uint128::zero();
@@ -581,74 +1847,110 @@ mod execute {
             let total_commission = config.transfer_commission * amount;
             treasury_amount = total_commission;
 
-            // split the commission 50/50 between the commission address and the treasury
+            // split the commission between the commission address and the treasury
             if let Some(commission_address) = commission_address.clone() {
                 let commission_address = deps.api.addr_validate(&commission_address)?;
                 if *recipient == commission_address {
                     return Err(ContractError::CommissionAddressSameAsRecipient {});
                 }
 
-                let expiration = ALLOWED_ADDRESSES.may_load(deps.storage, &commission_address)?;
-
                 // check if the commission address is allowed
-                if let Some(expiration) = expiration {
-                    if expiration.is_expired(&env.block) {
-                        return Err(ContractError::CommissionAddressExpired {
-                            address: commission_address.to_string(),
-                        });
-                    }
-                } else {
-                    return Err(ContractError::CommissionAddressNotFound {
-                        address: commission_address.to_string(),
-                    });
+                let allowed = load_allowed_address(deps.storage, env, &commission_address)?;
+                let mut earnings = COMMISSION_EARNINGS
+                    .may_load(deps.storage, &commission_address)?
+                    .unwrap_or_default();
+
+                commission_amount = total_commission
+                    - (Decimal::one() - allowed.commission_ratio) * total_commission;
+                // whatever exceeds the cap stays with the treasury
+                if let Some(cap) = allowed.commission_cap {
+                    commission_amount =
+                        std::cmp::min(commission_amount, cap.saturating_sub(earnings.amount));
                 }
+                treasury_amount = total_commission - commission_amount;
 
-                treasury_amount = treasury_amount.checked_div(2u128.into()).unwrap(); // divide by 2 here
-                commission_amount = total_commission.checked_sub(treasury_amount).unwrap();
+                if !commission_amount.is_zero() {
+                    earnings.amount += commission_amount;
+                    earnings.count += 1;
+                    COMMISSION_EARNINGS.save(deps.storage, &commission_address, &earnings)?;
+                }
 
                 // add the commission to the commission address
-                STAKE_DETAILS.update(
+                pay_transfer_commission(
                     deps.storage,
+                    config,
+                    env,
                     &commission_address,
-                    |stake_details| -> StdResult<_> {
-                        let mut stake_details = unwrap_stake_details(
-                            stake_details,
-                            config.denom.clone(),
-                            env.block.height,
-                        );
-                        stake_details.total.amount =
-                            stake_details.total.amount.checked_add(commission_amount)?;
-                        Ok(stake_details)
-                    },
+                    commission_amount,
+                    changes,
                 )?;
             }
 
-            // add the treasury commission to the treasury
-            STAKE_DETAILS.update(
-                deps.storage,
-                &config.treasury,
-                |stake_details| -> StdResult<_> {
-                    let mut stake_details =
-                        unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
-                    stake_details.total.amount =
-                        stake_details.total.amount.checked_add(treasury_amount)?;
-                    Ok(stake_details)
-                },
-            )?;
+            let mut treasury_commission = TREASURY_COMMISSION
+                .may_load(deps.storage)?
+                .unwrap_or_default();
+            treasury_commission.transfers += treasury_amount;
+            TREASURY_COMMISSION.save(deps.storage, &treasury_commission)?;
+
+            // add the treasury commission to the treasury recipients
+            for (recipient, recipient_amount) in split_treasury(&config.treasury, treasury_amount) {
+                pay_transfer_commission(
+                    deps.storage,
+                    config,
+                    env,
+                    &recipient,
+                    recipient_amount,
+                    changes,
+                )?;
+            }
             amount - total_commission
         };
         Ok((amount, treasury_amount, commission_amount))
     }
 
+    /// Credits transfer commission as stake, or queues it for unbonding to be paid liquid.
+    /// Either way the tokens stay delegated until then, so `TOTAL` doesn't change.
+    fn pay_transfer_commission(
+        storage: &mut dyn Storage,
+        config: &Config,
+        env: &Env,
+        address: &Addr,
+        amount: Uint128,
+        changes: &mut Vec<StakeChange>,
+    ) -> StdResult<()> {
+        match config.transfer_commission_payout {
+            PayoutMode::Stake => {
+                changes.push(credit_stake(storage, config, env, address, amount)?);
+                Ok(())
+            }
+            PayoutMode::Liquid => {
+                if !amount.is_zero() {
+                    PENDING_CLAIMS.update(storage, address, |claim| -> StdResult<_> {
+                        Ok(claim.unwrap_or_default() + amount)
+                    })?;
+                }
+                Ok(())
+            }
+        }
+    }
+
     pub fn undelegate_all(
-        deps: DepsMut,
+        mut deps: DepsMut,
         env: Env,
         info: MessageInfo,
+        resumable: bool,
     ) -> Result<Response, ContractError> {
         let config = CONFIG.load(deps.as_ref().storage)?;
         if config.owner != info.sender {
             return Err(ContractError::Unauthorized {});
         }
+        ensure_active(deps.storage)?;
+        let status = if resumable {
+            ContractStatus::WindingDown
+        } else {
+            ContractStatus::Terminated
+        };
+        STATUS.save(deps.storage, &status)?;
 
         let mut total_staked = Coin {
             amount: Uint128::zero(),
@@ -693,16 +1995,23 @@ mod execute {
         }
 
         // update STAKE_DETAILS with new stake details
+        let mut changes = vec![];
         for (addr, stake_details) in old_stake_details {
-            if stake_details.total.amount.is_zero() {
-                STAKE_DETAILS.remove(deps.storage, &addr);
+            let change = if stake_details.total.amount.is_zero() {
+                remove_stake_details(deps.storage, env.block.height, &addr)?
             } else {
-                STAKE_DETAILS.save(deps.storage, &addr, &stake_details)?;
-            }
+                save_stake_details(deps.storage, env.block.height, &addr, &stake_details)?
+            };
+            changes.push(change);
         }
 
+        // nothing is left to lock or to charge an early exit fee on
+        LOCKS.clear(deps.storage);
+        YOUNG_DEPOSITS.clear(deps.storage);
+
         // update CLAIM_DETAILS with new claim details
         for (addr, claim) in new_claim_details {
+            WIND_DOWN_SHARES.save(deps.storage, &addr, &claim.amount.amount)?;
             UNBONDING_CLAIMS.update(deps.storage, &addr, |claims| -> StdResult<_> {
                 let mut claims = claims.unwrap_or_default();
                 claims.push(claim);
@@ -713,22 +2022,243 @@ mod execute {
         // TODO: check if total corresponds to what total_staked: WARNING: Check rounding errors
         // let total = TOTAL.load(deps.storage)?; ---
 
-        let undelegate_msgs =
-            delegate_msgs_for_validators(deps.as_ref(), total_staked.clone(), false)?;
+        // Pending rewards are withdrawn before undelegating and shared like the wind down
+        // rewards, otherwise they'd be stranded in the contract
+        let reward = query::reward(deps.as_ref(), &env, config.clone())?.rewards[0].amount;
+        let commission_amount = config.restake_commission * reward;
+        let reward_msgs = distribute_msgs_for_validators(deps.as_ref())?;
+        let commission_msgs = share_wind_down_rewards(
+            deps.storage,
+            &env,
+            &config,
+            reward - commission_amount,
+            commission_amount,
+        )?;
+
+        // Stake in PENDING_REBONDS is unbonding already and reaches the contract before the
+        // claims are released, so it's paid out through them instead of being delegated back
+        let rebonding = PENDING_REBONDS
+            .may_load(deps.storage)?
+            .unwrap_or_default()
+            .iter()
+            .fold(Uint128::zero(), |sum, rebond| sum + rebond.amount.amount);
+        PENDING_REBONDS.remove(deps.storage);
+        let undelegate_msgs = undelegate_msgs_for_validators(
+            &mut deps,
+            &env,
+            &config,
+            coin(
+                total_staked.amount.saturating_sub(rebonding).u128(),
+                &config.denom,
+            ),
+        )?;
 
         // Update total amount of staked tokens
-        TOTAL.update(deps.storage, |total| -> StdResult<_> {
+        update_total(deps.storage, env.block.height, |total| -> StdResult<_> {
             Ok(coin(
                 (total.amount.checked_sub(total_staked.amount)?).u128(),
                 total.denom,
             ))
         })?;
 
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "undelegate_all",
+            Some(to_json_string(&total_staked)?),
+            Some(to_json_string(&status)?),
+        )?;
+
         Ok(Response::new()
             .add_attribute("action", "undelegate_all")
+            .add_attribute("resumable", resumable.to_string())
             .add_attribute("amount", total_staked.amount)
             .add_attribute("release_timestamp", release_timestamp.to_string())
-            .add_messages(undelegate_msgs))
+            .add_attribute("reward", reward)
+            .add_messages(reward_msgs)
+            .add_messages(undelegate_msgs)
+            .add_messages(commission_msgs)
+            .add_submessages(stake_changed_hooks(deps.storage, changes)?))
+    }
+
+    pub fn resume(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if config.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        let status = STATUS.may_load(deps.storage)?.unwrap_or_default();
+        if status != ContractStatus::WindingDown {
+            return Err(ContractError::CannotResume {});
+        }
+
+        STATUS.save(deps.storage, &ContractStatus::Active)?;
+        WIND_DOWN_SHARES.clear(deps.storage);
+        LAST_PAYMENT_BLOCK.save(deps.storage, &env.block.height)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "resume",
+            Some(to_json_string(&status)?),
+            Some(to_json_string(&ContractStatus::Active)?),
+        )?;
+
+        Ok(Response::new().add_attribute("action", "resume"))
+    }
+
+    /// Delegates all released pending rebonds to their target validator. Tokens without a
+    /// target, or meant for a validator which has been removed from the list since, are split
+    /// by current weights.
+    pub fn rebond(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+        ensure_active(deps.storage)?;
+        let (released, pending): (Vec<_>, Vec<_>) = PENDING_REBONDS
+            .may_load(deps.storage)?
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|rebond| rebond.release_timestamp.is_expired(&env.block));
+
+        if released.is_empty() {
+            return Err(ContractError::NoMaturedRebonds {});
+        }
+        PENDING_REBONDS.save(deps.storage, &pending)?;
+
+        let mut rebond_amount = Uint128::zero();
+        let mut msgs = vec![];
+        for rebond in released {
+            rebond_amount += rebond.amount.amount;
+            match rebond
+                .validator
+                .filter(|validator| VALIDATOR_LIST.has(deps.storage, validator.clone()))
+            {
+                Some(validator) => msgs.push(StakingMsg::Delegate {
+                    validator,
+                    amount: rebond.amount,
+                }),
+                None => msgs.append(&mut delegate_msgs_for_validators(
+                    deps.as_ref(),
+                    rebond.amount,
+                    true,
+                )?),
+            }
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "rebond")
+            .add_attribute("amount", rebond_amount)
+            .add_messages(msgs))
+    }
+
+    /// Redelegates the stake of overweight validators to underweight ones.
+    /// Can be called by anyone once allocation drift exceeds the configured threshold.
+    pub fn rebalance(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+        ensure_active(deps.storage)?;
+        let config = CONFIG.load(deps.storage)?;
+
+        let allocations = validator_allocations(deps.as_ref(), &env)?;
+        let drift = max_drift(&allocations);
+        if drift <= config.drift_threshold {
+            return Err(ContractError::DriftBelowThreshold {
+                drift,
+                threshold: config.drift_threshold,
+            });
+        }
+
+        let redelegate_msgs = compute_rebalance_msgs(&allocations, &config.denom);
+        let redelegate_msgs = route_redelegations(deps, &env, &config, redelegate_msgs)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "rebalance")
+            .add_attribute("drift", drift.to_string())
+            .add_messages(redelegate_msgs))
+    }
+
+    /// Removes `validator` from the list. Its stake is either redelegated to the remaining
+    /// validators right away, or undelegated and queued in `PENDING_REBONDS`.
+    pub fn remove_validator(
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        validator: String,
+        mode: RemovalMode,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if info.sender != config.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        if timelock_active(&config) {
+            return Err(ContractError::TimelockActive {});
+        }
+
+        if !VALIDATOR_LIST.has(deps.storage, validator.clone()) {
+            return Err(ContractError::ValidatorNotFound { validator });
+        }
+
+        let old_validator_list = VALIDATOR_LIST
+            .range(deps.storage, None, None, Ascending)
+            .collect::<StdResult<Vec<(String, Decimal)>>>()?;
+        let new_validator_list = remaining_validator_weights(&old_validator_list, &validator)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "remove_validator",
+            Some(to_json_string(&old_validator_list)?),
+            Some(to_json_string(&new_validator_list)?),
+        )?;
+
+        VALIDATOR_LIST.clear(deps.storage);
+        for (remaining, weight) in &new_validator_list {
+            VALIDATOR_LIST.save(deps.storage, remaining.clone(), weight)?;
+        }
+
+        let (msgs, mode) = match mode {
+            RemovalMode::Redelegate => {
+                let total_staked = TOTAL.load(deps.storage)?;
+                let redelegate_msgs = compute_redelegate_msgs(
+                    total_staked.amount,
+                    &config.denom,
+                    old_validator_list,
+                    new_validator_list,
+                )?;
+                let redelegate_msgs =
+                    route_redelegations(deps.branch(), &env, &config, redelegate_msgs)?;
+                (redelegate_msgs, "redelegate")
+            }
+            RemovalMode::Undelegate => {
+                let delegated = deps
+                    .querier
+                    .query_delegation(env.contract.address.clone(), validator.clone())?
+                    .map(|delegation| delegation.amount)
+                    .unwrap_or_else(|| coin(0, &config.denom));
+
+                let mut undelegate_msgs = vec![];
+                if !delegated.amount.is_zero() {
+                    add_unbonding_entry(deps.storage, &env, &config, &validator)?;
+
+                    let mut pending = PENDING_REBONDS.may_load(deps.storage)?.unwrap_or_default();
+                    pending.push(RebondDetails {
+                        validator: None,
+                        release_timestamp: config.unbonding_period.after(&env.block),
+                        amount: delegated.clone(),
+                    });
+                    PENDING_REBONDS.save(deps.storage, &pending)?;
+
+                    undelegate_msgs.push(StakingMsg::Undelegate {
+                        validator: validator.clone(),
+                        amount: delegated,
+                    });
+                }
+                (undelegate_msgs, "undelegate")
+            }
+        };
+
+        Ok(Response::new()
+            .add_attribute("action", "remove_validator")
+            .add_attribute("validator", validator)
+            .add_attribute("mode", mode)
+            .add_messages(msgs))
     }
 
     /// updates the allowed address list with the given address and expiration
@@ -738,6 +2268,8 @@ mod execute {
         info: MessageInfo,
         address: String,
         expiration: u64,
+        commission_ratio: Option<Decimal>,
+        commission_cap: Option<Uint128>,
     ) -> Result<Response, ContractError> {
         let config = CONFIG.load(deps.as_ref().storage)?;
         if config.owner != info.sender {
@@ -750,18 +2282,44 @@ mod execute {
             return Err(ContractError::ExpirationTooSoon {});
         }
 
+        let commission_ratio = commission_ratio.unwrap_or_else(|| Decimal::percent(50));
+        if commission_ratio > Decimal::one() {
+            return Err(ContractError::InvalidCommissionRatio {
+                ratio: commission_ratio,
+            });
+        }
+
         let address = deps.api.addr_validate(&address)?;
 
-        ALLOWED_ADDRESSES.update(deps.storage, &address, |_| -> StdResult<_> { Ok(exp) })?;
+        let old_allowed = ALLOWED_ADDRESSES.may_load(deps.storage, &address)?;
+        let allowed = AllowedAddress {
+            expires: exp,
+            commission_ratio,
+            commission_cap,
+        };
+        // commission earned so far is kept, so a new cap applies to the whole deal
+        ALLOWED_ADDRESSES.save(deps.storage, &address, &allowed)?;
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "update_allowed_address",
+            old_allowed
+                .map(|old| to_json_string(&(&address, old)))
+                .transpose()?,
+            Some(to_json_string(&(&address, allowed))?),
+        )?;
 
         Ok(Response::new()
             .add_attribute("action", "update_allowed_address")
             .add_attribute("allowed_address", address)
-            .add_attribute("expiration", expiration.to_string()))
+            .add_attribute("expiration", expiration.to_string())
+            .add_attribute("commission_ratio", commission_ratio.to_string()))
     }
 
     pub fn remove_allowed_address(
         deps: DepsMut,
+        env: Env,
         info: MessageInfo,
         address: String,
     ) -> Result<Response, ContractError> {
@@ -773,16 +2331,21 @@ mod execute {
         let address = deps.api.addr_validate(&address)?;
 
         // checks if address is in the list, otherwise returns an error
-        if ALLOWED_ADDRESSES
+        let old_allowed = ALLOWED_ADDRESSES
             .may_load(deps.storage, &address)?
-            .is_none()
-        {
-            return Err(ContractError::CommissionAddressNotFound {
+            .ok_or_else(|| ContractError::CommissionAddressNotFound {
                 address: address.to_string(),
-            });
-        }
+            })?;
 
         ALLOWED_ADDRESSES.remove(deps.storage, &address);
+        log_admin_action(
+            deps.storage,
+            &env,
+            &info.sender,
+            "remove_allowed_address",
+            Some(to_json_string(&(&address, old_allowed))?),
+            None,
+        )?;
 
         Ok(Response::new()
             .add_attribute("action", "remove_allowed_address")
@@ -797,6 +2360,7 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::Delegated { sender } => to_binary(&query::delegated(deps, sender)?),
         QueryMsg::TotalDelegated {} => to_binary(&query::total(deps)?),
         QueryMsg::Reward {} => to_binary(&query::reward(deps, &env, None)?),
+        QueryMsg::RewardsByValidator {} => to_binary(&query::rewards_by_validator(deps, env)?),
         QueryMsg::PendingClaim { sender } => {
             let sender = deps.api.addr_validate(&sender)?;
             to_binary(&query::pending_claim(deps, sender)?)
@@ -805,27 +2369,95 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
             let sender = deps.api.addr_validate(&sender)?;
             to_binary(&query::claims(deps, sender)?)
         }
-        QueryMsg::LastPaymentBlock {} => to_binary(&query::last_payment_block(deps)?),
-        QueryMsg::ValidatorWeight { validator } => to_binary(&query::validator(deps, validator)?),
-        QueryMsg::ValidatorList {} => to_binary(&query::validator_list(deps)?),
-        QueryMsg::AllowedAddr { address } => to_binary(&query::allowed_addr(deps, address)?),
-        QueryMsg::AllowedAddrList {} => to_binary(&query::allowed_addr_list(deps)?),
+        QueryMsg::LastPaymentBlock {} => to_binary(&query::last_payment_block(deps)?),
+        QueryMsg::ValidatorWeight { validator } => to_binary(&query::validator(deps, validator)?),
+        QueryMsg::ValidatorList {} => to_binary(&query::validator_list(deps)?),
+        QueryMsg::AllowedAddr { address } => to_binary(&query::allowed_addr(deps, address)?),
+        QueryMsg::AllowedAddrList {} => to_binary(&query::allowed_addr_list(deps)?),
+        QueryMsg::BlockedValidators {} => to_binary(&query::blocked_validators(deps, env)?),
+        QueryMsg::PendingRebonds {} => to_binary(&query::pending_rebonds(deps)?),
+        QueryMsg::UnbondingEntries {} => to_binary(&query::unbonding_entries(deps, env)?),
+        QueryMsg::AllocationDrift {} => to_binary(&query::allocation_drift(deps, env)?),
+        QueryMsg::ReferrerStats { referrer } => to_binary(&query::referrer_stats(deps, referrer)?),
+        QueryMsg::CommissionStats { address } => {
+            to_binary(&query::commission_stats(deps, address)?)
+        }
+        QueryMsg::PendingProposals {} => to_binary(&query::pending_proposals(deps)?),
+        QueryMsg::ProposalVotes { proposal_id } => {
+            to_binary(&query::proposal_votes(deps, env, proposal_id)?)
+        }
+        QueryMsg::Status {} => to_binary(&query::status(deps)?),
+        QueryMsg::AdminHistory { start_after, limit } => {
+            to_binary(&query::admin_history(deps, start_after, limit)?)
+        }
+        QueryMsg::StakeAtHeight { address, height } => {
+            to_binary(&query::stake_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::TotalAtHeight { height } => {
+            to_binary(&query::total_at_height(deps, env, height)?)
+        }
+        QueryMsg::VotingPowerAtHeight { address, height } => {
+            to_binary(&query::voting_power_at_height(deps, env, address, height)?)
+        }
+        QueryMsg::TotalPowerAtHeight { height } => {
+            to_binary(&query::total_power_at_height(deps, env, height)?)
+        }
+        QueryMsg::Dao {} => to_binary(&query::dao(deps)?),
+        QueryMsg::Info {} => to_binary(&query::info(deps)?),
+        QueryMsg::Hooks {} => to_binary(&query::hooks(deps)?),
+        QueryMsg::Locks { address } => to_binary(&query::locks(deps, address)?),
+        QueryMsg::SimulateUndelegate { address, amount } => {
+            to_binary(&query::simulate_undelegate(deps, env, address, amount)?)
+        }
+        QueryMsg::LiquidRewards { address } => to_binary(&query::liquid_rewards(deps, address)?),
+        QueryMsg::YieldHistory { start_after, limit } => {
+            to_binary(&query::yield_history(deps, start_after, limit)?)
+        }
+        QueryMsg::UserEarnings {
+            address,
+            start_after,
+            limit,
+        } => to_binary(&query::user_earnings(deps, address, start_after, limit)?),
+        QueryMsg::CurrentApr { window } => to_binary(&query::current_apr(deps, env, window)?),
     }
 }
 
 mod query {
     use crate::{
         msg::{
-            AllowedAddrListResponse, AllowedAddrResponse, PendingClaimResponse,
-            ValidatorWeightResponse, ValidatorsResponse,
+            AdminHistoryResponse, AllocationDriftResponse, AllowedAddrListResponse,
+            AllowedAddrResponse, BlockedValidatorsResponse, CommissionStatsResponse,
+            CurrentAprResponse, HooksResponse, InfoResponse, LiquidRewardsResponse, LocksResponse,
+            PendingClaimResponse, PendingProposalsResponse, PendingRebondsResponse,
+            ProposalVotesResponse, ReferrerStatsResponse, RewardsByValidatorResponse,
+            SimulateUndelegateResponse, StakeAtHeightResponse, StatusResponse,
+            TotalAtHeightResponse, TotalPowerAtHeightResponse, UnbondingEntriesResponse,
+            UserEarningsResponse, ValidatorDrift, ValidatorRewards, ValidatorWeightResponse,
+            ValidatorsResponse, VotingPowerAtHeightResponse, YieldHistoryResponse,
+        },
+        state::{
+            AllowedAddress, ADMIN_HISTORY, ALLOWED_ADDRESSES, COMMISSION_EARNINGS, DEFAULT_VOTES,
+            GOV_PROPOSALS, HOOKS, LIQUID_REWARDS, LOCKS, PENDING_CLAIMS, PENDING_PROPOSALS,
+            PENDING_REBONDS, REDELEGATIONS, REFERRER_STATS, REWARD_MODES, STAKE_SNAPSHOTS, STATUS,
+            TOTAL_POWER_SNAPSHOT, TOTAL_STAKE_SNAPSHOT, TREASURY_COMMISSION, USER_EARNINGS,
+            VALIDATOR_LIST, YIELD_HISTORY, YIELD_RECIPIENTS,
         },
-        state::{ALLOWED_ADDRESSES, PENDING_CLAIMS, VALIDATOR_LIST},
     };
-    use cosmwasm_std::Order::Ascending;
+    use cosmwasm_std::Order::{Ascending, Descending};
+    use cw_storage_plus::Bound;
     use cw_utils::Expiration;
 
+    use super::utils::{
+        active_unbonding_entries, early_exit_fee, take_young_deposits, tally_votes,
+        validator_allocations, validator_drift, vote_participation, young_deposits,
+    };
     use super::*;
 
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+    const DEFAULT_APR_WINDOW: u64 = 3600 * 24 * 30; // 30 days
+    const SECONDS_PER_YEAR: u64 = 3600 * 24 * 365;
+
     pub fn config(deps: Deps) -> StdResult<ConfigResponse> {
         Ok(ConfigResponse {
             config: CONFIG.load(deps.storage)?,
@@ -835,10 +2467,14 @@ mod query {
     pub fn delegated(deps: Deps, sender: String) -> StdResult<DelegatedResponse> {
         let sender_addr = deps.api.addr_validate(&sender)?;
 
+        let yield_recipient = YIELD_RECIPIENTS.may_load(deps.storage, &sender_addr)?;
         let delegated = if let Some(details) = STAKE_DETAILS.may_load(deps.storage, &sender_addr)? {
             details
         } else {
-            return Ok(DelegatedResponse { delegated: vec![] });
+            return Ok(DelegatedResponse {
+                delegated: vec![],
+                yield_recipient,
+            });
         };
         let partial_stakes: Uint128 = delegated
             .partials
@@ -853,6 +2489,7 @@ mod query {
                 total_staked,
                 total_earnings: delegated.earnings,
             }],
+            yield_recipient,
         })
     }
 
@@ -901,6 +2538,49 @@ mod query {
         Ok(reward_response)
     }
 
+    pub fn rewards_by_validator(deps: Deps, env: Env) -> StdResult<RewardsByValidatorResponse> {
+        let config = CONFIG.load(deps.storage)?;
+
+        let mut validators = vec![];
+        for data in VALIDATOR_LIST.range(deps.storage, None, None, Ascending) {
+            let (validator, weight) = data?;
+            let (delegated, rewards) = match deps
+                .querier
+                .query_delegation(env.contract.address.clone(), validator.clone())?
+            {
+                Some(delegation) => (delegation.amount, delegation.accumulated_rewards),
+                None => (coin(0, &config.denom), vec![]),
+            };
+            validators.push(ValidatorRewards {
+                validator,
+                weight,
+                delegated,
+                rewards,
+                share: Decimal::zero(),
+            });
+        }
+
+        let denom_rewards: Vec<Uint128> = validators
+            .iter()
+            .map(|validator_rewards| {
+                validator_rewards
+                    .rewards
+                    .iter()
+                    .filter(|reward| reward.denom == config.denom)
+                    .map(|reward| reward.amount)
+                    .sum::<Uint128>()
+            })
+            .collect();
+        let total_reward: Uint128 = denom_rewards.iter().copied().sum();
+        if !total_reward.is_zero() {
+            for (validator_rewards, reward) in validators.iter_mut().zip(denom_rewards) {
+                validator_rewards.share = Decimal::from_ratio(reward, total_reward);
+            }
+        }
+
+        Ok(RewardsByValidatorResponse { validators })
+    }
+
     pub fn pending_claim(deps: Deps, sender: Addr) -> StdResult<PendingClaimResponse> {
         let amount = PENDING_CLAIMS
             .load(deps.storage, &sender)
@@ -937,36 +2617,854 @@ mod query {
     pub fn allowed_addr(deps: Deps, address: String) -> StdResult<AllowedAddrResponse> {
         let address = deps.api.addr_validate(&address)?;
 
-        let expires = ALLOWED_ADDRESSES.load(deps.storage, &address)?;
-        Ok(AllowedAddrResponse { expires })
+        let allowed = ALLOWED_ADDRESSES.load(deps.storage, &address)?;
+        let earnings = COMMISSION_EARNINGS
+            .may_load(deps.storage, &address)?
+            .unwrap_or_default();
+        Ok(AllowedAddrResponse {
+            expires: allowed.expires,
+            commission_ratio: allowed.commission_ratio,
+            commission_cap: allowed.commission_cap,
+            commission_earned: earnings.amount,
+        })
     }
 
     /// no max limit required as this list is not expected to exceed 20-30 items.
     pub fn allowed_addr_list(deps: Deps) -> StdResult<AllowedAddrListResponse> {
         let allowed_list = ALLOWED_ADDRESSES
             .range(deps.storage, None, None, Ascending)
-            .collect::<StdResult<Vec<(Addr, Expiration)>>>()?;
+            .collect::<StdResult<Vec<(Addr, AllowedAddress)>>>()?;
         Ok(AllowedAddrListResponse { allowed_list })
     }
-}
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
-    let storage_version = ensure_from_older_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    pub fn blocked_validators(deps: Deps, env: Env) -> StdResult<BlockedValidatorsResponse> {
+        let mut validators = vec![];
+        for data in REDELEGATIONS.range(deps.storage, None, None, Ascending) {
+            let (validator, redelegations) = data?;
+            // report the latest completion among redelegations which didn't mature yet
+            let blocked_until = redelegations
+                .into_iter()
+                .map(|redelegation| redelegation.completion)
+                .filter(|completion| !completion.is_expired(&env.block))
+                .reduce(|latest, completion| {
+                    if completion > latest {
+                        completion
+                    } else {
+                        latest
+                    }
+                });
+            if let Some(blocked_until) = blocked_until {
+                validators.push((validator, blocked_until));
+            }
+        }
+        Ok(BlockedValidatorsResponse { validators })
+    }
+
+    pub fn pending_rebonds(deps: Deps) -> StdResult<PendingRebondsResponse> {
+        Ok(PendingRebondsResponse {
+            rebonds: PENDING_REBONDS.may_load(deps.storage)?.unwrap_or_default(),
+        })
+    }
+
+    pub fn pending_proposals(deps: Deps) -> StdResult<PendingProposalsResponse> {
+        let proposals = PENDING_PROPOSALS
+            .range(deps.storage, None, None, Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(PendingProposalsResponse { proposals })
+    }
+
+    pub fn proposal_votes(
+        deps: Deps,
+        env: Env,
+        proposal_id: u64,
+    ) -> StdResult<ProposalVotesResponse> {
+        // proposals which weren't opened have no votes yet
+        let height = GOV_PROPOSALS
+            .may_load(deps.storage, proposal_id)?
+            .map(|proposal| proposal.height)
+            .unwrap_or(env.block.height);
+        let tally = tally_votes(deps.storage, proposal_id, height)?;
+        let voted: Uint128 = tally.iter().map(|(_, stake)| stake).sum();
+        Ok(ProposalVotesResponse {
+            tally,
+            participation: vote_participation(deps.storage, voted, height)?,
+            default_vote: DEFAULT_VOTES.may_load(deps.storage, proposal_id)?,
+        })
+    }
+
+    pub fn status(deps: Deps) -> StdResult<StatusResponse> {
+        Ok(StatusResponse {
+            status: STATUS.may_load(deps.storage)?.unwrap_or_default(),
+        })
+    }
+
+    pub fn admin_history(
+        deps: Deps,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<AdminHistoryResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.map(Bound::exclusive);
+
+        let entries = ADMIN_HISTORY
+            .range(deps.storage, start, None, Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(AdminHistoryResponse { entries })
+    }
+
+    pub fn stake_at_height(
+        deps: Deps,
+        env: Env,
+        address: String,
+        height: Option<u64>,
+    ) -> StdResult<StakeAtHeightResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        let (stake, height) = match height {
+            Some(height) => (
+                STAKE_SNAPSHOTS.may_load_at_height(deps.storage, &address, height)?,
+                height,
+            ),
+            None => (
+                STAKE_SNAPSHOTS.may_load(deps.storage, &address)?,
+                env.block.height,
+            ),
+        };
+        Ok(StakeAtHeightResponse {
+            stake: stake.unwrap_or_default(),
+            height,
+        })
+    }
+
+    pub fn total_at_height(
+        deps: Deps,
+        env: Env,
+        height: Option<u64>,
+    ) -> StdResult<TotalAtHeightResponse> {
+        let (total, height) = match height {
+            Some(height) => (
+                TOTAL_STAKE_SNAPSHOT.may_load_at_height(deps.storage, height)?,
+                height,
+            ),
+            None => (
+                TOTAL_STAKE_SNAPSHOT.may_load(deps.storage)?,
+                env.block.height,
+            ),
+        };
+        Ok(TotalAtHeightResponse {
+            total: total.unwrap_or_default(),
+            height,
+        })
+    }
+
+    pub fn voting_power_at_height(
+        deps: Deps,
+        env: Env,
+        address: String,
+        height: Option<u64>,
+    ) -> StdResult<VotingPowerAtHeightResponse> {
+        let StakeAtHeightResponse { stake, height } = stake_at_height(deps, env, address, height)?;
+        Ok(VotingPowerAtHeightResponse {
+            power: stake,
+            height,
+        })
+    }
+
+    pub fn total_power_at_height(
+        deps: Deps,
+        env: Env,
+        height: Option<u64>,
+    ) -> StdResult<TotalPowerAtHeightResponse> {
+        let (power, height) = match height {
+            Some(height) => (
+                TOTAL_POWER_SNAPSHOT.may_load_at_height(deps.storage, height)?,
+                height,
+            ),
+            None => (
+                TOTAL_POWER_SNAPSHOT.may_load(deps.storage)?,
+                env.block.height,
+            ),
+        };
+        Ok(TotalPowerAtHeightResponse {
+            power: power.unwrap_or_default(),
+            height,
+        })
+    }
+
+    pub fn yield_history(
+        deps: Deps,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<YieldHistoryResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.map(Bound::exclusive);
+
+        let entries = YIELD_HISTORY
+            .range(deps.storage, start, None, Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(YieldHistoryResponse { entries })
+    }
+
+    pub fn user_earnings(
+        deps: Deps,
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<UserEarningsResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.map(Bound::exclusive);
+
+        let earnings = USER_EARNINGS
+            .prefix(&address)
+            .range(deps.storage, start, None, Ascending)
+            .take(limit)
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(UserEarningsResponse { earnings })
+    }
+
+    /// Annualizes the rewards of restakes within the window. The first restake in the window
+    /// only marks its start, as its reward was earned before.
+    pub fn current_apr(deps: Deps, env: Env, window: Option<u64>) -> StdResult<CurrentAprResponse> {
+        let window = window.unwrap_or(DEFAULT_APR_WINDOW);
+        let since = env.block.time.seconds().saturating_sub(window);
+
+        let entries = YIELD_HISTORY
+            .range(deps.storage, None, None, Descending)
+            .map(|entry| entry.map(|(_, entry)| entry))
+            .take_while(|entry| {
+                entry
+                    .as_ref()
+                    .map_or(true, |entry| entry.time.seconds() >= since)
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+
+        let (first, last) = match (entries.last(), entries.first()) {
+            (Some(first), Some(last)) if last.time > first.time => (first, last),
+            _ => {
+                return Ok(CurrentAprResponse {
+                    apr: Decimal::zero(),
+                    apy: Decimal::zero(),
+                    restakes: 0,
+                    window,
+                })
+            }
+        };
+        let span = last.time.seconds() - first.time.seconds();
+        let restakes = entries.len() - 1;
+
+        let rate: Decimal = entries[..restakes]
+            .iter()
+            .filter(|entry| !entry.total_before.is_zero())
+            .map(|entry| Decimal::from_ratio(entry.reward, entry.total_before))
+            .sum();
+        let apr = rate * Decimal::from_ratio(SECONDS_PER_YEAR, span);
+
+        // compounding as often as restakes happened within the window
+        let periods = (SECONDS_PER_YEAR * restakes as u64 / span).max(1);
+        let periods = u32::try_from(periods).unwrap_or(u32::MAX);
+        let apy = (Decimal::one() + apr / Decimal::from_ratio(periods, 1u32))
+            .checked_pow(periods)?
+            - Decimal::one();
+
+        Ok(CurrentAprResponse {
+            apr,
+            apy,
+            restakes: restakes as u32,
+            window,
+        })
+    }
+
+    pub fn liquid_rewards(deps: Deps, address: String) -> StdResult<LiquidRewardsResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        Ok(LiquidRewardsResponse {
+            mode: REWARD_MODES
+                .may_load(deps.storage, &address)?
+                .unwrap_or_default(),
+            amount: LIQUID_REWARDS
+                .may_load(deps.storage, &address)?
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn locks(deps: Deps, address: String) -> StdResult<LocksResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        let locks = LOCKS
+            .prefix(&address)
+            .range(deps.storage, None, None, Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(LocksResponse { locks })
+    }
+
+    pub fn simulate_undelegate(
+        deps: Deps,
+        env: Env,
+        address: String,
+        amount: Uint128,
+    ) -> StdResult<SimulateUndelegateResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        let config = CONFIG.load(deps.storage)?;
+        let staked = STAKE_DETAILS
+            .may_load(deps.storage, &address)?
+            .map(|stake_details| stake_details.staked())
+            .unwrap_or_default();
+        let mut deposits = young_deposits(deps.storage, &config, &env, &address)?;
+        let taken = take_young_deposits(&mut deposits, staked, amount);
+        let fee = early_exit_fee(&config, &taken);
+        let fee_free_height = taken
+            .iter()
+            .map(|deposit| deposit.join_height + config.early_exit_blocks)
+            .max()
+            .unwrap_or(env.block.height);
+        Ok(SimulateUndelegateResponse {
+            amount,
+            fee,
+            received: amount.saturating_sub(fee),
+            fee_free_height,
+        })
+    }
+
+    pub fn hooks(deps: Deps) -> StdResult<HooksResponse> {
+        let hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+        Ok(HooksResponse {
+            hooks: hooks.into_iter().map(String::from).collect(),
+        })
+    }
+
+    pub fn dao(deps: Deps) -> StdResult<Addr> {
+        Ok(CONFIG.load(deps.storage)?.owner)
+    }
+
+    pub fn info(deps: Deps) -> StdResult<InfoResponse> {
+        Ok(InfoResponse {
+            info: cw2::get_contract_version(deps.storage)?,
+        })
+    }
+
+    pub fn allocation_drift(deps: Deps, env: Env) -> StdResult<AllocationDriftResponse> {
+        let allocations = validator_allocations(deps, &env)?;
+        let total_delegated: Uint128 = allocations.iter().map(|(_, _, amount)| amount).sum();
+
+        let validators: Vec<ValidatorDrift> = allocations
+            .iter()
+            .map(|(validator, target_weight, delegated)| {
+                let (actual_share, drift) =
+                    validator_drift(*target_weight, *delegated, total_delegated);
+                ValidatorDrift {
+                    validator: validator.clone(),
+                    target_weight: *target_weight,
+                    delegated: *delegated,
+                    actual_share,
+                    drift,
+                }
+            })
+            .collect();
+        let max_drift = validators
+            .iter()
+            .map(|validator| validator.drift)
+            .max()
+            .unwrap_or_default();
+
+        Ok(AllocationDriftResponse {
+            validators,
+            max_drift,
+            threshold: CONFIG.load(deps.storage)?.drift_threshold,
+        })
+    }
+
+    pub fn unbonding_entries(deps: Deps, env: Env) -> StdResult<UnbondingEntriesResponse> {
+        let config = CONFIG.load(deps.storage)?;
+        let entries = VALIDATOR_LIST
+            .keys(deps.storage, None, None, Ascending)
+            .map(|validator| {
+                let validator = validator?;
+                let used = active_unbonding_entries(deps.storage, &env, &validator)?.len() as u64;
+                Ok((validator, config.max_entries.saturating_sub(used)))
+            })
+            .collect::<StdResult<_>>()?;
+        Ok(UnbondingEntriesResponse { entries })
+    }
+
+    pub fn referrer_stats(deps: Deps, referrer: String) -> StdResult<ReferrerStatsResponse> {
+        let referrer = deps.api.addr_validate(&referrer)?;
+
+        let stats = REFERRER_STATS
+            .may_load(deps.storage, &referrer)?
+            .unwrap_or_default();
+        Ok(ReferrerStatsResponse {
+            referred_users: stats.referred_users,
+            commission_earned: stats.commission_earned,
+        })
+    }
+
+    pub fn commission_stats(
+        deps: Deps,
+        address: Option<String>,
+    ) -> StdResult<CommissionStatsResponse> {
+        let treasury = TREASURY_COMMISSION
+            .may_load(deps.storage)?
+            .unwrap_or_default();
+
+        let (address, referral_commission) = match address {
+            Some(address) => {
+                let address = deps.api.addr_validate(&address)?;
+                let earnings = COMMISSION_EARNINGS
+                    .may_load(deps.storage, &address)?
+                    .unwrap_or_default();
+                let referral = REFERRER_STATS
+                    .may_load(deps.storage, &address)?
+                    .unwrap_or_default();
+                (Some(earnings), Some(referral.commission_earned))
+            }
+            None => (None, None),
+        };
+
+        Ok(CommissionStatsResponse {
+            treasury,
+            address,
+            referral_commission,
+        })
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let storage_version = ensure_from_older_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    migrate_config(deps, env, &storage_version, msg)?;
+    Ok(Response::new())
+}
+
+pub mod utils {
+
+    use std::ops::Add;
+
+    use cosmwasm_std::{Api, Fraction, Order::Ascending, Storage, VoteOption};
+
+    use cosmwasm_std::to_vec;
+    use serde::Serialize;
+
+    use crate::state::{
+        AdminAction, AllowedAddress, CommissionBaseline, ContractStatus, GovProposal, LockTier,
+        RebondDetails, RedelegationDetails, ADMIN_HISTORY, ADMIN_HISTORY_COUNT, ALLOWED_ADDRESSES,
+        COMMISSION_BASELINES, GOV_PROPOSALS, HOOKS, LOCKS, PENDING_REBONDS, REDELEGATIONS,
+        STAKE_SNAPSHOTS, STATUS, TOTAL_POWER_SNAPSHOT, TOTAL_STAKE_SNAPSHOT, UNBONDING_ENTRIES,
+        VALIDATOR_LIST, VOTES, YIELD_HISTORY_COUNT, YOUNG_DEPOSITS,
+    };
+
+    use super::*;
+
+    /// Saves stake details of `address` and records its stake at `height`
+    pub fn save_stake_details(
+        storage: &mut dyn Storage,
+        height: u64,
+        address: &Addr,
+        stake_details: &StakeDetails,
+    ) -> StdResult<StakeChange> {
+        let old = STAKE_SNAPSHOTS
+            .may_load(storage, address)?
+            .unwrap_or_default();
+        let new = stake_details.staked();
+        STAKE_DETAILS.save(storage, address, stake_details)?;
+        STAKE_SNAPSHOTS.save(storage, address, &new, height)?;
+        update_total_power(storage, height, old, new)?;
+        Ok(StakeChange {
+            addr: address.clone(),
+            old,
+            new,
+        })
+    }
+
+    pub fn update_stake_details<A>(
+        storage: &mut dyn Storage,
+        height: u64,
+        address: &Addr,
+        action: A,
+    ) -> StdResult<StakeChange>
+    where
+        A: FnOnce(Option<StakeDetails>) -> StdResult<StakeDetails>,
+    {
+        let stake_details = action(STAKE_DETAILS.may_load(storage, address)?)?;
+        save_stake_details(storage, height, address, &stake_details)
+    }
+
+    pub fn remove_stake_details(
+        storage: &mut dyn Storage,
+        height: u64,
+        address: &Addr,
+    ) -> StdResult<StakeChange> {
+        let old = STAKE_SNAPSHOTS
+            .may_load(storage, address)?
+            .unwrap_or_default();
+        STAKE_DETAILS.remove(storage, address);
+        STAKE_SNAPSHOTS.remove(storage, address, height)?;
+        update_total_power(storage, height, old, Uint128::zero())?;
+        Ok(StakeChange {
+            addr: address.clone(),
+            old,
+            new: Uint128::zero(),
+        })
+    }
+
+    /// Moves the total voting power at `height` by the change of a single stake
+    fn update_total_power(
+        storage: &mut dyn Storage,
+        height: u64,
+        old: Uint128,
+        new: Uint128,
+    ) -> StdResult<()> {
+        if old == new {
+            return Ok(());
+        }
+        let total = TOTAL_POWER_SNAPSHOT.may_load(storage)?.unwrap_or_default();
+        TOTAL_POWER_SNAPSHOT.save(storage, &(total + new - old), height)
+    }
+
+    /// Notifies every registered hook about each stake which actually changed
+    pub fn stake_changed_hooks(
+        storage: &dyn Storage,
+        changes: Vec<StakeChange>,
+    ) -> StdResult<Vec<SubMsg>> {
+        let hooks = HOOKS.may_load(storage)?.unwrap_or_default();
+        let mut msgs = vec![];
+        for change in changes
+            .into_iter()
+            .filter(|change| change.old != change.new)
+        {
+            let msg = to_binary(&StakeChangedHookMsg::StakeChangedHook(change))?;
+            for hook in &hooks {
+                msgs.push(SubMsg::new(WasmMsg::Execute {
+                    contract_addr: hook.to_string(),
+                    msg: msg.clone(),
+                    funds: vec![],
+                }));
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Saves total stake and records it at `height`
+    pub fn save_total(storage: &mut dyn Storage, height: u64, total: &Coin) -> StdResult<()> {
+        TOTAL.save(storage, total)?;
+        TOTAL_STAKE_SNAPSHOT.save(storage, &total.amount, height)
+    }
+
+    pub fn update_total<A>(storage: &mut dyn Storage, height: u64, action: A) -> StdResult<Coin>
+    where
+        A: FnOnce(Coin) -> StdResult<Coin>,
+    {
+        let total = action(TOTAL.load(storage)?)?;
+        save_total(storage, height, &total)?;
+        Ok(total)
+    }
+
+    /// Rejects actions which would put new tokens to work while the contract is winding down
+    pub fn ensure_active(storage: &dyn Storage) -> Result<(), ContractError> {
+        if STATUS.may_load(storage)?.unwrap_or_default() != ContractStatus::Active {
+            return Err(ContractError::NotActive {});
+        }
+        Ok(())
+    }
+
+    pub fn to_json_string<T: Serialize>(value: &T) -> StdResult<String> {
+        Ok(String::from_utf8(to_vec(value)?)?)
+    }
+
+    /// Appends an owner action to the admin history
+    pub fn log_admin_action(
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        action: &str,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    ) -> StdResult<()> {
+        log_executed_action(storage, env, sender, None, action, old_value, new_value)
+    }
+
+    /// Logs an admin action of `sender` that was carried out by `executor`, if someone else
+    pub fn log_executed_action(
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        executor: Option<&Addr>,
+        action: &str,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    ) -> StdResult<()> {
+        let id = ADMIN_HISTORY_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+        ADMIN_HISTORY_COUNT.save(storage, &id)?;
+        ADMIN_HISTORY.save(
+            storage,
+            id,
+            &AdminAction {
+                height: env.block.height,
+                time: env.block.time,
+                sender: sender.clone(),
+                executor: executor.cloned(),
+                action: action.to_owned(),
+                old_value,
+                new_value,
+            },
+        )
+    }
+
+    /// Id of a new restake, shared by its `YIELD_HISTORY` and `USER_EARNINGS` entries
+    pub fn next_restake_id(storage: &mut dyn Storage) -> StdResult<u64> {
+        let id = YIELD_HISTORY_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+        YIELD_HISTORY_COUNT.save(storage, &id)?;
+        Ok(id)
+    }
+
+    /// Loads a gov proposal opened for votes, failing once its voting period is over
+    pub fn load_open_proposal(
+        storage: &dyn Storage,
+        env: &Env,
+        proposal_id: u64,
+    ) -> Result<GovProposal, ContractError> {
+        match GOV_PROPOSALS.may_load(storage, proposal_id)? {
+            Some(proposal) if env.block.time < proposal.voting_end => Ok(proposal),
+            _ => Err(ContractError::ProposalNotOpen { proposal_id }),
+        }
+    }
+
+    /// Stake counted for governance votes at the start of block `height`, including partial
+    /// stakes
+    pub fn voting_power(storage: &dyn Storage, address: &Addr, height: u64) -> StdResult<Uint128> {
+        Ok(STAKE_SNAPSHOTS
+            .may_load_at_height(storage, address, height)?
+            .unwrap_or_default())
+    }
+
+    /// Stake of the voters at `height` for every vote option
+    pub fn tally_votes(
+        storage: &dyn Storage,
+        proposal_id: u64,
+        height: u64,
+    ) -> StdResult<Vec<(VoteOption, Uint128)>> {
+        let mut tally = vec![
+            (VoteOption::Yes, Uint128::zero()),
+            (VoteOption::No, Uint128::zero()),
+            (VoteOption::Abstain, Uint128::zero()),
+            (VoteOption::NoWithVeto, Uint128::zero()),
+        ];
+        for vote in VOTES
+            .prefix(proposal_id)
+            .range(storage, None, None, Ascending)
+        {
+            let (voter, option) = vote?;
+            let power = voting_power(storage, &voter, height)?;
+            if let Some((_, stake)) = tally.iter_mut().find(|(o, _)| *o == option) {
+                *stake += power;
+            }
+        }
+        Ok(tally)
+    }
+
+    /// Share of the total voting power at `height` that the voted stake represents
+    pub fn vote_participation(
+        storage: &dyn Storage,
+        voted: Uint128,
+        height: u64,
+    ) -> StdResult<Decimal> {
+        let total = TOTAL_POWER_SNAPSHOT
+            .may_load_at_height(storage, height)?
+            .unwrap_or_default();
+        if total.is_zero() {
+            return Ok(Decimal::zero());
+        }
+        Ok(Decimal::from_ratio(voted, total).min(Decimal::one()))
+    }
+
+    pub fn validate_vote_quorum(quorum: Decimal) -> Result<Decimal, ContractError> {
+        if quorum > Decimal::one() {
+            return Err(ContractError::InvalidVoteQuorum { quorum });
+        }
+        Ok(quorum)
+    }
+
+    /// Stake of `address` which is still locked
+    pub fn locked_stake(storage: &dyn Storage, env: &Env, address: &Addr) -> StdResult<Uint128> {
+        LOCKS
+            .prefix(address)
+            .range(storage, None, None, Ascending)
+            .map(|lock| lock.map(|(_, lock)| lock))
+            .filter(|lock| {
+                lock.as_ref()
+                    .map_or(true, |lock| env.block.time < lock.unlocks_at)
+            })
+            .map(|lock| lock.map(|lock| lock.amount))
+            .sum()
+    }
+
+    /// Fails if `address` would be left with less stake than it has locked
+    pub fn ensure_unlocked(
+        storage: &dyn Storage,
+        env: &Env,
+        address: &Addr,
+        remaining: Uint128,
+    ) -> Result<(), ContractError> {
+        let locked = locked_stake(storage, env, address)?;
+        if remaining < locked {
+            return Err(ContractError::StakeLocked { locked, remaining });
+        }
+        Ok(())
+    }
+
+    /// Extra reward weight of locks which were active for the whole period since the last
+    /// payment; locks made since then are still partial stakes. Boosted amount never exceeds
+    /// the fully weighted `stake` of the address.
+    pub fn lock_boost(
+        storage: &dyn Storage,
+        env: &Env,
+        address: &Addr,
+        last_payment_block: u64,
+        stake: Uint128,
+    ) -> StdResult<Decimal> {
+        let mut remaining = stake;
+        let mut boost = Decimal::zero();
+        for lock in LOCKS.prefix(address).range(storage, None, None, Ascending) {
+            let (_, lock) = lock?;
+            if lock.start_height > last_payment_block || env.block.time >= lock.unlocks_at {
+                continue;
+            }
+            let amount = std::cmp::min(lock.amount, remaining);
+            remaining -= amount;
+            boost += Decimal::from_ratio(amount, 1u128) * (lock.multiplier - Decimal::one());
+        }
+        Ok(boost)
+    }
+
+    pub fn validate_lock_tiers(tiers: Vec<LockTier>) -> Result<Vec<LockTier>, ContractError> {
+        for (i, tier) in tiers.iter().enumerate() {
+            if tier.duration == 0
+                || tier.multiplier < Decimal::one()
+                || tiers[..i]
+                    .iter()
+                    .any(|other| other.duration == tier.duration)
+            {
+                return Err(ContractError::InvalidLockTier {
+                    duration: tier.duration,
+                });
+            }
+        }
+        Ok(tiers)
+    }
+
+    pub fn validate_unlock_penalty(penalty: Decimal) -> Result<Decimal, ContractError> {
+        if penalty > Decimal::one() {
+            return Err(ContractError::InvalidUnlockPenalty { penalty });
+        }
+        Ok(penalty)
+    }
+
+    pub fn validate_early_exit_fee(fee: Decimal) -> Result<Decimal, ContractError> {
+        if fee > Decimal::one() {
+            return Err(ContractError::InvalidEarlyExitFee { fee });
+        }
+        Ok(fee)
+    }
+
+    pub fn validate_ratio(ratio: Decimal) -> Result<Decimal, ContractError> {
+        if ratio > Decimal::one() {
+            return Err(ContractError::InvalidCommissionRatio { ratio });
+        }
+        Ok(ratio)
+    }
 
-    migrate_config(deps, env, &storage_version, msg)?;
-    Ok(Response::new())
-}
+    /// Checks that drift threshold is a non-zero ratio
+    pub fn validate_drift_threshold(threshold: Decimal) -> Result<Decimal, ContractError> {
+        if threshold.is_zero() || threshold > Decimal::one() {
+            return Err(ContractError::InvalidDriftThreshold { threshold });
+        }
+        Ok(threshold)
+    }
 
-pub mod utils {
+    /// Checks that commission doesn't exceed the hard cap
+    pub fn validate_commission(commission: Decimal) -> Result<Decimal, ContractError> {
+        let max = Decimal::percent(MAX_COMMISSION_PERCENT);
+        if commission > max {
+            return Err(ContractError::CommissionTooHigh { commission, max });
+        }
+        Ok(commission)
+    }
 
-    use std::ops::Add;
+    /// Checks that new commission is within the hard cap and doesn't move too far from the old one
+    pub fn validate_commission_change(
+        old: Decimal,
+        new: Decimal,
+    ) -> Result<Decimal, ContractError> {
+        let new = validate_commission(new)?;
+        let change = if new > old { new - old } else { old - new };
+        let max_change = Decimal::percent(MAX_COMMISSION_CHANGE_PERCENT);
+        if change > max_change {
+            return Err(ContractError::CommissionChangeTooLarge {
+                old,
+                new,
+                max_change,
+            });
+        }
+        Ok(new)
+    }
 
-    use cosmwasm_std::{Fraction, Order::Ascending};
+    fn active_commission_baseline(
+        storage: &dyn Storage,
+        env: &Env,
+        kind: &str,
+    ) -> StdResult<Option<CommissionBaseline>> {
+        Ok(COMMISSION_BASELINES
+            .may_load(storage, kind)?
+            .filter(|baseline| env.block.height < baseline.height + COMMISSION_CHANGE_BLOCKS))
+    }
 
-    use crate::state::VALIDATOR_LIST;
+    /// Commission `kind` changes are measured from: its value before the current change window,
+    /// or `current` if no window is open
+    pub fn commission_baseline(
+        storage: &dyn Storage,
+        env: &Env,
+        kind: &str,
+        current: Decimal,
+    ) -> StdResult<Decimal> {
+        Ok(active_commission_baseline(storage, env, kind)?
+            .map_or(current, |baseline| baseline.commission))
+    }
 
-    use super::*;
+    /// Opens a change window for commission `kind` if it changed while none was open
+    pub fn record_commission_change(
+        storage: &mut dyn Storage,
+        env: &Env,
+        kind: &str,
+        old: Decimal,
+        new: Decimal,
+    ) -> StdResult<()> {
+        if old == new || active_commission_baseline(storage, env, kind)?.is_some() {
+            return Ok(());
+        }
+        COMMISSION_BASELINES.save(
+            storage,
+            kind,
+            &CommissionBaseline {
+                commission: old,
+                height: env.block.height,
+            },
+        )
+    }
+
+    /// Minimum time between unbondings so that at most `max_entries` are in flight at once
+    pub fn unbonding_cooldown(
+        unbonding_period: Duration,
+        max_entries: u64,
+    ) -> Result<Duration, ContractError> {
+        if max_entries == 0 {
+            return Err(ContractError::ZeroMaxEntries {});
+        }
+        match unbonding_period {
+            Duration::Time(0) | Duration::Height(0) => Err(ContractError::ZeroUnbondingPeriod {}),
+            Duration::Time(period) => Ok(Duration::Time(period / max_entries)),
+            Duration::Height(period) => Ok(Duration::Height(period / max_entries)),
+        }
+    }
 
     pub fn check_unbonding_cooldown(
         deps: &DepsMut,
@@ -1022,6 +3520,138 @@ pub mod utils {
         Ok(msgs)
     }
 
+    /// Unbonding entries of `validator` which haven't completed yet
+    pub fn active_unbonding_entries(
+        storage: &dyn Storage,
+        env: &Env,
+        validator: &str,
+    ) -> StdResult<Vec<Expiration>> {
+        let mut entries = UNBONDING_ENTRIES
+            .may_load(storage, validator.to_owned())?
+            .unwrap_or_default();
+        entries.retain(|completion| !completion.is_expired(&env.block));
+        Ok(entries)
+    }
+
+    /// Occupies one unbonding entry of `validator`, failing if none is left
+    pub fn add_unbonding_entry(
+        storage: &mut dyn Storage,
+        env: &Env,
+        config: &Config,
+        validator: &str,
+    ) -> Result<(), ContractError> {
+        let mut entries = active_unbonding_entries(storage, env, validator)?;
+        if entries.len() as u64 >= config.max_entries {
+            return Err(ContractError::MaxUnbondingEntries {
+                validator: validator.to_owned(),
+            });
+        }
+        entries.push(config.unbonding_period.after(&env.block));
+        UNBONDING_ENTRIES.save(storage, validator.to_owned(), &entries)?;
+        Ok(())
+    }
+
+    pub struct ValidatorUnbonding {
+        pub validator: String,
+        pub weight: Decimal,
+        pub has_free_entry: bool,
+        pub delegated: Uint128,
+    }
+
+    /// Splits `amount` by validator weights, capping every share at the validator's delegated
+    /// stake. Whatever exceeds it, and the share of a validator without a free unbonding entry,
+    /// is moved to the other validators which still have one, in list order and up to their
+    /// delegated stake. Fails if the amount can't be placed anywhere.
+    pub fn plan_undelegations(
+        amount: Uint128,
+        validators: &[ValidatorUnbonding],
+    ) -> Result<Vec<(String, Uint128)>, ContractError> {
+        let mut planned: Vec<(String, Uint128, Uint128)> = vec![];
+        let mut rerouted = Uint128::zero();
+        let mut full_validator = None;
+
+        for validator in validators {
+            let share =
+                amount.multiply_ratio(validator.weight.numerator(), validator.weight.denominator());
+            if validator.has_free_entry {
+                let capped = std::cmp::min(share, validator.delegated);
+                rerouted += share - capped;
+                planned.push((validator.validator.clone(), capped, validator.delegated));
+            } else if !share.is_zero() {
+                rerouted += share;
+                if full_validator.is_none() {
+                    full_validator = Some(validator.validator.clone());
+                }
+            }
+        }
+
+        for (_, planned_amount, delegated) in planned.iter_mut() {
+            if rerouted.is_zero() {
+                break;
+            }
+            let moved = std::cmp::min(delegated.saturating_sub(*planned_amount), rerouted);
+            *planned_amount += moved;
+            rerouted -= moved;
+        }
+
+        if !rerouted.is_zero() {
+            return Err(match full_validator {
+                Some(validator) => ContractError::MaxUnbondingEntries { validator },
+                None => ContractError::NotEnoughToUndelegate {
+                    wanted: amount,
+                    have: validators
+                        .iter()
+                        .fold(Uint128::zero(), |sum, validator| sum + validator.delegated),
+                },
+            });
+        }
+
+        Ok(planned
+            .into_iter()
+            .filter(|(_, amount, _)| !amount.is_zero())
+            .map(|(validator, amount, _)| (validator, amount))
+            .collect())
+    }
+
+    /// Creates undelegate messages split by validator weights, respecting the unbonding
+    /// entries limit of every validator (see `plan_undelegations`)
+    pub fn undelegate_msgs_for_validators(
+        deps: &mut DepsMut,
+        env: &Env,
+        config: &Config,
+        amount: Coin,
+    ) -> Result<Vec<StakingMsg>, ContractError> {
+        let validator_list = VALIDATOR_LIST
+            .range(deps.storage, None, None, Ascending)
+            .collect::<StdResult<Vec<(String, Decimal)>>>()?;
+
+        let mut validators = vec![];
+        for (validator, weight) in validator_list {
+            let used_entries = active_unbonding_entries(deps.storage, env, &validator)?.len();
+            let delegated = deps
+                .querier
+                .query_delegation(env.contract.address.clone(), validator.clone())?
+                .map(|delegation| delegation.amount.amount)
+                .unwrap_or_default();
+            validators.push(ValidatorUnbonding {
+                validator,
+                weight,
+                has_free_entry: (used_entries as u64) < config.max_entries,
+                delegated,
+            });
+        }
+
+        let mut msgs = vec![];
+        for (validator, stake_amount) in plan_undelegations(amount.amount, &validators)? {
+            add_unbonding_entry(deps.storage, env, config, &validator)?;
+            msgs.push(StakingMsg::Undelegate {
+                validator,
+                amount: coin(stake_amount.u128(), &amount.denom),
+            });
+        }
+        Ok(msgs)
+    }
+
     pub fn distribute_msgs_for_validators(deps: Deps) -> StdResult<Vec<DistributionMsg>> {
         VALIDATOR_LIST
             .range(deps.storage, None, None, Ascending)
@@ -1127,6 +3757,547 @@ pub mod utils {
         Ok(msgs)
     }
 
+    /// Returns true if `validator` received a redelegation which hasn't completed yet.
+    /// The SDK doesn't allow transitive redelegations, so such a validator can't be a source.
+    pub fn is_redelegation_blocked(
+        storage: &dyn Storage,
+        env: &Env,
+        validator: &str,
+    ) -> StdResult<bool> {
+        let redelegations = REDELEGATIONS
+            .may_load(storage, validator.to_owned())?
+            .unwrap_or_default();
+        Ok(redelegations
+            .iter()
+            .any(|redelegation| !redelegation.completion.is_expired(&env.block)))
+    }
+
+    /// Returns true if `src_validator` can't redelegate to `dst_validator` right now, either
+    /// because it's blocked or because the pair has used up its `max_entries`
+    fn is_redelegation_unavailable(
+        storage: &dyn Storage,
+        env: &Env,
+        config: &Config,
+        src_validator: &str,
+        dst_validator: &str,
+    ) -> StdResult<bool> {
+        if is_redelegation_blocked(storage, env, src_validator)? {
+            return Ok(true);
+        }
+        let entries = REDELEGATIONS
+            .may_load(storage, dst_validator.to_owned())?
+            .unwrap_or_default()
+            .iter()
+            .filter(|redelegation| {
+                redelegation.src_validator == src_validator
+                    && !redelegation.completion.is_expired(&env.block)
+            })
+            .count();
+        Ok(entries as u64 >= config.max_entries)
+    }
+
+    /// Records every planned redelegation with its completion time. When the planned source
+    /// can't redelegate but stays listed, the other listed validators are tried in order, as
+    /// long as their delegation not moved elsewhere covers the amount. Otherwise the source is
+    /// undelegated; the tokens are queued in `PENDING_REBONDS` and delegated to the destination
+    /// once released.
+    pub fn route_redelegations(
+        deps: DepsMut,
+        env: &Env,
+        config: &Config,
+        msgs: Vec<StakingMsg>,
+    ) -> Result<Vec<StakingMsg>, ContractError> {
+        let completion = config.unbonding_period.after(&env.block);
+
+        // delegation every source is already planned to give away
+        let mut outgoing: HashMap<String, Uint128> = HashMap::new();
+        for msg in &msgs {
+            if let StakingMsg::Redelegate {
+                src_validator,
+                amount,
+                ..
+            } = msg
+            {
+                *outgoing.entry(src_validator.clone()).or_default() += amount.amount;
+            }
+        }
+        let validators = VALIDATOR_LIST
+            .keys(deps.storage, None, None, Ascending)
+            .collect::<StdResult<Vec<String>>>()?;
+
+        let mut routed_msgs = vec![];
+        let mut rebonds = vec![];
+        for msg in msgs {
+            match msg {
+                // empty redelegations don't move anything, so they shouldn't block the destination
+                StakingMsg::Redelegate { amount, .. } if amount.amount.is_zero() => continue,
+                StakingMsg::Redelegate {
+                    src_validator,
+                    dst_validator,
+                    amount,
+                } => {
+                    let mut source = None;
+                    if !is_redelegation_unavailable(
+                        deps.storage,
+                        env,
+                        config,
+                        &src_validator,
+                        &dst_validator,
+                    )? {
+                        source = Some(src_validator.clone());
+                    } else if validators.contains(&src_validator) {
+                        // a removed validator has to be emptied, so only a listed source
+                        // can keep its stake for now
+                        for validator in &validators {
+                            if *validator == src_validator
+                                || *validator == dst_validator
+                                || is_redelegation_unavailable(
+                                    deps.storage,
+                                    env,
+                                    config,
+                                    validator,
+                                    &dst_validator,
+                                )?
+                            {
+                                continue;
+                            }
+                            let delegated = deps
+                                .querier
+                                .query_delegation(env.contract.address.clone(), validator.clone())?
+                                .map(|delegation| delegation.amount.amount)
+                                .unwrap_or_default();
+                            let spare = delegated.saturating_sub(
+                                outgoing.get(validator).copied().unwrap_or_default(),
+                            );
+                            if spare >= amount.amount {
+                                *outgoing.entry(src_validator.clone()).or_default() -=
+                                    amount.amount;
+                                *outgoing.entry(validator.clone()).or_default() += amount.amount;
+                                source = Some(validator.clone());
+                                break;
+                            }
+                        }
+                    }
+
+                    let src_validator = match source {
+                        Some(source) => source,
+                        None => {
+                            add_unbonding_entry(deps.storage, env, config, &src_validator)?;
+                            rebonds.push(RebondDetails {
+                                validator: Some(dst_validator),
+                                release_timestamp: completion,
+                                amount: amount.clone(),
+                            });
+                            routed_msgs.push(StakingMsg::Undelegate {
+                                validator: src_validator,
+                                amount,
+                            });
+                            continue;
+                        }
+                    };
+
+                    REDELEGATIONS.update(
+                        deps.storage,
+                        dst_validator.clone(),
+                        |redelegations| -> StdResult<_> {
+                            let mut redelegations = redelegations.unwrap_or_default();
+                            // matured entries don't block anything anymore
+                            redelegations.retain(|redelegation| {
+                                !redelegation.completion.is_expired(&env.block)
+                            });
+                            redelegations.push(RedelegationDetails {
+                                src_validator: src_validator.clone(),
+                                amount: amount.clone(),
+                                completion,
+                            });
+                            Ok(redelegations)
+                        },
+                    )?;
+                    routed_msgs.push(StakingMsg::Redelegate {
+                        src_validator,
+                        dst_validator,
+                        amount,
+                    });
+                }
+                msg => routed_msgs.push(msg),
+            }
+        }
+
+        if !rebonds.is_empty() {
+            let mut pending = PENDING_REBONDS.may_load(deps.storage)?.unwrap_or_default();
+            pending.append(&mut rebonds);
+            PENDING_REBONDS.save(deps.storage, &pending)?;
+        }
+
+        Ok(routed_msgs)
+    }
+
+    /// Weights of the validator list without `removed`, scaled up so they sum to 1 again.
+    /// The last remaining validator takes the rounding remainder.
+    pub fn remaining_validator_weights(
+        validator_list: &[(String, Decimal)],
+        removed: &str,
+    ) -> Result<Vec<(String, Decimal)>, ContractError> {
+        let remaining: Vec<&(String, Decimal)> = validator_list
+            .iter()
+            .filter(|(validator, _)| validator != removed)
+            .collect();
+        let remaining_weight = remaining
+            .iter()
+            .fold(Decimal::zero(), |sum, (_, weight)| sum + *weight);
+        if remaining_weight.is_zero() {
+            return Err(ContractError::CannotRemoveLastValidator {});
+        }
+
+        let mut weights = vec![];
+        let mut assigned = Decimal::zero();
+        for (index, (validator, weight)) in remaining.iter().enumerate() {
+            let weight = if index + 1 == remaining.len() {
+                Decimal::one() - assigned
+            } else {
+                Decimal::from_ratio(weight.numerator(), remaining_weight.numerator())
+            };
+            assigned += weight;
+            weights.push((validator.clone(), weight));
+        }
+        Ok(weights)
+    }
+
+    /// Validates treasury recipients, whose weights have to sum to 1
+    pub fn validate_treasury(
+        api: &dyn Api,
+        treasury: Vec<(String, Decimal)>,
+    ) -> Result<Vec<(Addr, Decimal)>, ContractError> {
+        let mut sum = Decimal::zero();
+        let mut recipients = vec![];
+        for (recipient, weight) in treasury {
+            sum += weight;
+            recipients.push((api.addr_validate(&recipient)?, weight));
+        }
+
+        if sum != Decimal::one() {
+            return Err(ContractError::InvalidTreasury {});
+        }
+        Ok(recipients)
+    }
+
+    /// Splits `amount` between treasury recipients by weight. The last recipient takes the
+    /// rounding remainder; recipients with nothing to receive are skipped.
+    pub fn split_treasury(treasury: &[(Addr, Decimal)], amount: Uint128) -> Vec<(Addr, Uint128)> {
+        let mut remaining = amount;
+        let mut shares = vec![];
+        for (index, (recipient, weight)) in treasury.iter().enumerate() {
+            let share = if index + 1 == treasury.len() {
+                remaining
+            } else {
+                amount.multiply_ratio(weight.numerator(), weight.denominator())
+            };
+            remaining -= share;
+            if !share.is_zero() {
+                shares.push((recipient.clone(), share));
+            }
+        }
+        shares
+    }
+
+    /// Checks a new delegation of `amount` by `address` against the configured minimum and caps
+    pub fn check_deposit_limits(
+        storage: &dyn Storage,
+        config: &Config,
+        address: &Addr,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        if amount < config.min_delegation {
+            return Err(ContractError::DelegationTooSmall {
+                min: config.min_delegation,
+                amount,
+            });
+        }
+
+        let total = TOTAL.load(storage)?.amount + amount;
+        if !config.deposit_cap.is_zero() && total > config.deposit_cap {
+            return Err(ContractError::DepositCapExceeded {
+                cap: config.deposit_cap,
+                total,
+            });
+        }
+
+        check_address_cap(storage, config, address, amount)
+    }
+
+    /// Checks that receiving `amount` keeps the stake of `address` within the address cap
+    pub fn check_address_cap(
+        storage: &dyn Storage,
+        config: &Config,
+        address: &Addr,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        let staked = STAKE_DETAILS
+            .may_load(storage, address)?
+            .map(|stake_details| stake_details.staked())
+            .unwrap_or_default()
+            + amount;
+        if !config.address_cap.is_zero() && staked > config.address_cap {
+            return Err(ContractError::AddressCapExceeded {
+                cap: config.address_cap,
+                staked,
+            });
+        }
+        Ok(())
+    }
+
+    /// Deposits of `address` which are still in the early exit fee period, oldest first
+    pub fn young_deposits(
+        storage: &dyn Storage,
+        config: &Config,
+        env: &Env,
+        address: &Addr,
+    ) -> StdResult<Vec<Stake>> {
+        let mut deposits = YOUNG_DEPOSITS
+            .may_load(storage, address)?
+            .unwrap_or_default();
+        deposits
+            .retain(|deposit| env.block.height < deposit.join_height + config.early_exit_blocks);
+        Ok(deposits)
+    }
+
+    pub fn save_young_deposits(
+        storage: &mut dyn Storage,
+        config: &Config,
+        env: &Env,
+        address: &Addr,
+        mut deposits: Vec<Stake>,
+    ) -> StdResult<()> {
+        deposits
+            .retain(|deposit| env.block.height < deposit.join_height + config.early_exit_blocks);
+        if deposits.is_empty() {
+            YOUNG_DEPOSITS.remove(storage, address);
+            Ok(())
+        } else {
+            YOUNG_DEPOSITS.save(storage, address, &deposits)
+        }
+    }
+
+    /// Takes `amount` out of a stake of `staked` which includes the young `deposits`. Older
+    /// stake goes first, so only what exceeds it is taken from the deposits, newest first.
+    /// Returns the taken parts, which keep their `join_height`.
+    pub fn take_young_deposits(
+        deposits: &mut Vec<Stake>,
+        staked: Uint128,
+        amount: Uint128,
+    ) -> Vec<Stake> {
+        let young = deposits
+            .iter()
+            .fold(Uint128::zero(), |sum, deposit| sum + deposit.amount.amount);
+        let mut remaining = amount.saturating_sub(staked.saturating_sub(young));
+
+        let mut taken = vec![];
+        while let Some(deposit) = deposits.last_mut() {
+            if remaining.is_zero() {
+                break;
+            }
+            let moved = std::cmp::min(deposit.amount.amount, remaining);
+            deposit.amount.amount -= moved;
+            remaining -= moved;
+            taken.push(Stake {
+                amount: coin(moved.u128(), &deposit.amount.denom),
+                join_height: deposit.join_height,
+            });
+            if deposit.amount.amount.is_zero() {
+                deposits.pop();
+            }
+        }
+        taken.reverse();
+        taken
+    }
+
+    /// Fee for undelegating the `taken` young deposits
+    pub fn early_exit_fee(config: &Config, taken: &[Stake]) -> Uint128 {
+        let amount = taken
+            .iter()
+            .fold(Uint128::zero(), |sum, deposit| sum + deposit.amount.amount);
+        config.early_exit_fee * amount
+    }
+
+    /// Adds `amount` to the fully weighted stake of `address`
+    pub fn credit_stake(
+        storage: &mut dyn Storage,
+        config: &Config,
+        env: &Env,
+        address: &Addr,
+        amount: Uint128,
+    ) -> StdResult<StakeChange> {
+        update_stake_details(
+            storage,
+            env.block.height,
+            address,
+            |stake_details| -> StdResult<_> {
+                let mut stake_details =
+                    unwrap_stake_details(stake_details, config.denom.clone(), env.block.height);
+                stake_details.total.amount = stake_details.total.amount.checked_add(amount)?;
+                Ok(stake_details)
+            },
+        )
+    }
+
+    /// Loads the allowed list entry of `address`, failing if it's missing or expired
+    pub fn load_allowed_address(
+        storage: &dyn Storage,
+        env: &Env,
+        address: &Addr,
+    ) -> Result<AllowedAddress, ContractError> {
+        match ALLOWED_ADDRESSES.may_load(storage, address)? {
+            Some(allowed) if allowed.expires.is_expired(&env.block) => {
+                Err(ContractError::CommissionAddressExpired {
+                    address: address.to_string(),
+                })
+            }
+            Some(allowed) => Ok(allowed),
+            None => Err(ContractError::CommissionAddressNotFound {
+                address: address.to_string(),
+            }),
+        }
+    }
+
+    /// Target weight and currently delegated amount of every validator in the list
+    pub fn validator_allocations(
+        deps: Deps,
+        env: &Env,
+    ) -> StdResult<Vec<(String, Decimal, Uint128)>> {
+        VALIDATOR_LIST
+            .range(deps.storage, None, None, Ascending)
+            .map(|validator| {
+                let (validator, weight) = validator?;
+                let delegated = deps
+                    .querier
+                    .query_delegation(env.contract.address.clone(), validator.clone())?
+                    .map(|delegation| delegation.amount.amount)
+                    .unwrap_or_default();
+                Ok((validator, weight, delegated))
+            })
+            .collect()
+    }
+
+    /// Returns the actual share of a validator and its absolute difference to the target
+    /// weight. Nothing delegated at all counts as no drift.
+    pub fn validator_drift(
+        target_weight: Decimal,
+        delegated: Uint128,
+        total_delegated: Uint128,
+    ) -> (Decimal, Decimal) {
+        if total_delegated.is_zero() {
+            return (target_weight, Decimal::zero());
+        }
+        let actual_share = Decimal::from_ratio(delegated, total_delegated);
+        let drift = if target_weight > actual_share {
+            target_weight - actual_share
+        } else {
+            actual_share - target_weight
+        };
+        (actual_share, drift)
+    }
+
+    pub fn max_drift(allocations: &[(String, Decimal, Uint128)]) -> Decimal {
+        let total_delegated: Uint128 = allocations.iter().map(|(_, _, amount)| amount).sum();
+        allocations
+            .iter()
+            .map(|(_, weight, delegated)| validator_drift(*weight, *delegated, total_delegated).1)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Splits `total_delegated` by target weights and returns how much each validator
+    /// is above (surplus) or below (deficit) its target
+    fn allocation_differences(
+        allocations: &[(String, Decimal, Uint128)],
+        total_delegated: Uint128,
+    ) -> (Vec<(String, Uint128)>, Vec<(String, Uint128)>) {
+        let mut surplus = vec![];
+        let mut deficit = vec![];
+        for (validator, weight, delegated) in allocations {
+            let target = total_delegated.multiply_ratio(weight.numerator(), weight.denominator());
+            if *delegated > target {
+                surplus.push((validator.clone(), *delegated - target));
+            } else if *delegated < target {
+                deficit.push((validator.clone(), target - *delegated));
+            }
+        }
+        (surplus, deficit)
+    }
+
+    /// Delegates `reward` to validators below their target weight, proportionally to how much
+    /// they are missing. Falls back to splitting by weights if nobody is missing anything.
+    pub fn underweight_delegate_msgs(
+        allocations: &[(String, Decimal, Uint128)],
+        reward: Coin,
+    ) -> Vec<StakingMsg> {
+        let total_delegated: Uint128 = allocations.iter().map(|(_, _, amount)| amount).sum();
+        let (_, deficit) = allocation_differences(allocations, total_delegated + reward.amount);
+        let total_deficit: Uint128 = deficit.iter().map(|(_, amount)| amount).sum();
+
+        let amounts: Vec<(String, Uint128)> = if total_deficit.is_zero() {
+            allocations
+                .iter()
+                .map(|(validator, weight, _)| {
+                    let amount = reward
+                        .amount
+                        .multiply_ratio(weight.numerator(), weight.denominator());
+                    (validator.clone(), amount)
+                })
+                .collect()
+        } else {
+            deficit
+                .into_iter()
+                .map(|(validator, missing)| {
+                    (
+                        validator,
+                        reward.amount.multiply_ratio(missing, total_deficit),
+                    )
+                })
+                .collect()
+        };
+
+        amounts
+            .into_iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .map(|(validator, amount)| StakingMsg::Delegate {
+                validator,
+                amount: coin(amount.u128(), &reward.denom),
+            })
+            .collect()
+    }
+
+    /// Computes redelegations which move the stake of overweight validators
+    /// to underweight ones, so that the actual allocation matches the target weights
+    pub fn compute_rebalance_msgs(
+        allocations: &[(String, Decimal, Uint128)],
+        denom: &str,
+    ) -> Vec<StakingMsg> {
+        let total_delegated: Uint128 = allocations.iter().map(|(_, _, amount)| amount).sum();
+        let (mut surplus, deficit) = allocation_differences(allocations, total_delegated);
+
+        let mut msgs = vec![];
+        let mut source = 0;
+        for (addr_to, mut missing) in deficit {
+            while !missing.is_zero() && source < surplus.len() {
+                let (addr_from, available) = &mut surplus[source];
+                let amount = std::cmp::min(missing, *available);
+                msgs.push(redelegate_msg(
+                    addr_from,
+                    &addr_to,
+                    amount,
+                    denom.to_string(),
+                ));
+                missing -= amount;
+                *available -= amount;
+                if available.is_zero() {
+                    source += 1;
+                }
+            }
+        }
+        msgs
+    }
+
     fn redelegate_msg(from: &str, to: &str, amount: Uint128, denom: String) -> StakingMsg {
         StakingMsg::Redelegate {
             src_validator: from.to_owned(),
@@ -1154,17 +4325,51 @@ pub mod utils {
     #[cfg(test)]
     mod tests {
         use cosmwasm_std::{
+            coin,
             testing::{mock_dependencies, mock_env},
-            Addr, Decimal,
+            Addr, CosmosMsg, Decimal, GovMsg, Uint128, VoteOption, WeightedVoteOption,
         };
         use cw_utils::{Duration, Expiration};
 
         use crate::{
-            state::{Config, LATEST_UNBONDING},
+            state::{
+                Config, GovProposal, PayoutMode, StakeDetails, CONFIG, GOV_PROPOSALS,
+                LATEST_UNBONDING, VOTES,
+            },
             ContractError,
         };
 
-        use super::check_unbonding_cooldown;
+        use super::super::{execute, DEFAULT_REFERRAL_PERIOD};
+        use super::{check_unbonding_cooldown, save_stake_details, save_total};
+
+        fn config() -> Config {
+            Config {
+                min_unbonding_cooldown: Duration::Time(1),
+                unbonding_period: Duration::Time(7),
+                denom: "token".to_string(),
+                owner: Addr::unchecked("creator"),
+                treasury: vec![(Addr::unchecked("treasury"), Decimal::one())],
+                transfer_commission: Decimal::percent(10),
+                restake_commission: Decimal::percent(10),
+                max_entries: 7,
+                drift_threshold: Decimal::percent(5),
+                referral_commission: Decimal::zero(),
+                referral_period: Duration::Time(DEFAULT_REFERRAL_PERIOD),
+                restake_commission_payout: PayoutMode::Liquid,
+                transfer_commission_payout: PayoutMode::Stake,
+                timelock_delay: Duration::Time(0),
+                vote_quorum: Decimal::percent(10),
+                lock_tiers: vec![],
+                early_unlock_penalty: Decimal::percent(10),
+                early_exit_fee: Decimal::zero(),
+                early_exit_blocks: 0,
+                early_exit_treasury_share: Decimal::percent(50),
+                deposit_cap: Uint128::zero(),
+                address_cap: Uint128::zero(),
+                min_delegation: Uint128::zero(),
+                min_undelegation: Uint128::zero(),
+            }
+        }
 
         #[test]
         fn test_minimum_unbonding_check() {
@@ -1177,15 +4382,7 @@ pub mod utils {
                 .unwrap();
 
             // ... arrange ...
-            let config = Config {
-                min_unbonding_cooldown: Duration::Time(1),
-                unbonding_period: Duration::Time(7),
-                denom: "token".to_string(),
-                owner: Addr::unchecked("creator"),
-                treasury: Addr::unchecked("treasury"),
-                transfer_commission: Decimal::percent(10),
-                restake_commission: Decimal::percent(10),
-            };
+            let config = config();
 
             // unbonding period not expired
             assert!(check_unbonding_cooldown(&deps.as_mut(), &config, &env).is_err());
@@ -1202,5 +4399,63 @@ pub mod utils {
             env.block.time = env.block.time.plus_seconds(1);
             assert!(check_unbonding_cooldown(&deps.as_mut(), &config, &env).is_ok());
         }
+
+        #[test]
+        fn cast_vote_weights_sum_up_to_one() {
+            let mut deps = mock_dependencies();
+            let env = mock_env();
+            CONFIG.save(&mut deps.storage, &config()).unwrap();
+
+            let votes = [
+                ("voter1", VoteOption::Yes),
+                ("voter2", VoteOption::No),
+                ("voter3", VoteOption::Abstain),
+            ];
+            for (voter, vote) in votes {
+                let voter = Addr::unchecked(voter);
+                let stake_details = StakeDetails {
+                    total: coin(100, "token"),
+                    partials: vec![],
+                    earnings: Uint128::zero(),
+                    start_height: 1,
+                };
+                save_stake_details(&mut deps.storage, 1, &voter, &stake_details).unwrap();
+                VOTES.save(&mut deps.storage, (1, &voter), &vote).unwrap();
+            }
+            save_total(&mut deps.storage, 1, &coin(300, "token")).unwrap();
+            GOV_PROPOSALS
+                .save(
+                    &mut deps.storage,
+                    1,
+                    &GovProposal {
+                        height: 2,
+                        voting_end: env.block.time.plus_seconds(1),
+                    },
+                )
+                .unwrap();
+
+            let res = execute::cast_vote(deps.as_mut(), env, 1).unwrap();
+            // the last option takes the rounding error
+            assert_eq!(
+                res.messages[0].msg,
+                CosmosMsg::Gov(GovMsg::VoteWeighted {
+                    proposal_id: 1,
+                    options: vec![
+                        WeightedVoteOption {
+                            option: VoteOption::Yes,
+                            weight: Decimal::raw(333_333_333_333_333_333),
+                        },
+                        WeightedVoteOption {
+                            option: VoteOption::No,
+                            weight: Decimal::raw(333_333_333_333_333_333),
+                        },
+                        WeightedVoteOption {
+                            option: VoteOption::Abstain,
+                            weight: Decimal::raw(333_333_333_333_333_334),
+                        },
+                    ],
+                })
+            );
+        }
     }
 }
@@ -1,7 +1,7 @@
 use cw_utils::Expiration;
 use thiserror::Error;
 
-use cosmwasm_std::{StdError, Uint128};
+use cosmwasm_std::{Decimal, StdError, Uint128};
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
@@ -34,6 +34,9 @@ pub enum ContractError {
     #[error("Validators weights do not sum to 1.0")]
     InvalidValidatorList {},
 
+    #[error("Treasury weights do not sum to 1.0")]
+    InvalidTreasury {},
+
     #[error("Delegation not found")]
     DelegationNotFound {},
 
@@ -46,6 +49,111 @@ pub enum ContractError {
     #[error("Allowance of Commission Address {address} is expired")]
     CommissionAddressExpired { address: String },
 
+    #[error("Commission ratio {ratio} can't be greater than 1.0")]
+    InvalidCommissionRatio { ratio: Decimal },
+
+    #[error("Commission {commission} exceeds the maximum of {max}")]
+    CommissionTooHigh { commission: Decimal, max: Decimal },
+
+    #[error("Commission can't change from {old} to {new} within one change window; maximum change is {max_change}")]
+    CommissionChangeTooLarge {
+        old: Decimal,
+        new: Decimal,
+        max_change: Decimal,
+    },
+
+    #[error("Drift threshold {threshold} must be greater than 0 and at most 1.0")]
+    InvalidDriftThreshold { threshold: Decimal },
+
+    #[error("Vote quorum {quorum} can't be greater than 1.0")]
+    InvalidVoteQuorum { quorum: Decimal },
+
+    #[error("Lock tier of {duration} seconds needs a non-zero, unique duration and a multiplier of at least 1.0")]
+    InvalidLockTier { duration: u64 },
+
+    #[error("Early unlock penalty {penalty} can't be greater than 1.0")]
+    InvalidUnlockPenalty { penalty: Decimal },
+
+    #[error("No lock tier with a duration of {duration} seconds")]
+    InvalidLockDuration { duration: u64 },
+
+    #[error("Lock {id} not found")]
+    LockNotFound { id: u64 },
+
+    #[error("{locked} of the stake is locked, but only {remaining} would be left")]
+    StakeLocked { locked: Uint128, remaining: Uint128 },
+
+    #[error("Early exit fee {fee} can't be greater than 1.0")]
+    InvalidEarlyExitFee { fee: Decimal },
+
+    #[error("Deposit cap of {cap} reached, total would be {total}")]
+    DepositCapExceeded { cap: Uint128, total: Uint128 },
+
+    #[error("Address cap of {cap} reached, stake would be {staked}")]
+    AddressCapExceeded { cap: Uint128, staked: Uint128 },
+
+    #[error("Delegation of {amount} is below the minimum of {min}")]
+    DelegationTooSmall { min: Uint128, amount: Uint128 },
+
+    #[error("Undelegation of {amount} is below the minimum of {min}")]
+    UndelegationTooSmall { min: Uint128, amount: Uint128 },
+
+    #[error("Unbonding period can't be zero")]
+    ZeroUnbondingPeriod {},
+
+    #[error("Max unbonding entries can't be zero")]
+    ZeroMaxEntries {},
+
+    #[error("Changes have to be proposed and executed after the timelock delay")]
+    TimelockActive {},
+
+    #[error("Pending proposal {id} not found")]
+    ProposalNotFound { id: u64 },
+
+    #[error("Pending proposal can't be executed before {execute_after}")]
+    TimelockNotExpired { execute_after: Expiration },
+
+    #[error("Contract is winding down; deposits and transfers are disabled")]
+    NotActive {},
+
+    #[error("Contract can only be resumed from a resumable wind-down")]
+    CannotResume {},
+
+    #[error("Gov proposal {proposal_id} isn't open for votes")]
+    ProposalNotOpen { proposal_id: u64 },
+
+    #[error("Gov proposal {proposal_id} is already open for votes")]
+    ProposalAlreadyOpen { proposal_id: u64 },
+
+    #[error("Only delegators can vote")]
+    NoVotingPower {},
+
+    #[error(
+        "Participation {participation} is below the quorum {quorum} and there is no default vote"
+    )]
+    QuorumNotReached {
+        participation: Decimal,
+        quorum: Decimal,
+    },
+
+    #[error("Hook {addr} is already registered")]
+    HookAlreadyRegistered { addr: String },
+
+    #[error("Hook {addr} is not registered")]
+    HookNotRegistered { addr: String },
+
+    #[error("No liquid rewards to claim")]
+    NoLiquidRewards {},
+
+    #[error("Yield ratio {ratio} has to be above 0 and at most 1.0")]
+    InvalidYieldRatio { ratio: Decimal },
+
+    #[error("Sender can't redirect its yield to itself")]
+    SelfYieldRecipient {},
+
+    #[error("Sender can't refer itself")]
+    SelfReferral {},
+
     #[error("Commission address may not be the same as the recipient")]
     CommissionAddressSameAsRecipient {},
 
@@ -55,6 +163,21 @@ pub enum ContractError {
         latest_unbonding: Expiration,
     },
 
+    #[error("Validator {validator} has no unbonding entries left and its share can't be moved to other validators")]
+    MaxUnbondingEntries { validator: String },
+
+    #[error("Allocation drift {drift} doesn't exceed the threshold {threshold}")]
+    DriftBelowThreshold { drift: Decimal, threshold: Decimal },
+
+    #[error("None of the pending rebonds has been released yet")]
+    NoMaturedRebonds {},
+
+    #[error("Validator {validator} not found in the validator list")]
+    ValidatorNotFound { validator: String },
+
+    #[error("Can't remove the last validator with a non-zero weight")]
+    CannotRemoveLastValidator {},
+
     #[error("Semver parsing error: {0}")]
     SemVer(String),
 }
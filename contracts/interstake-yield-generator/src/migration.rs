@@ -3,11 +3,23 @@ use schemars::JsonSchema;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Decimal, DepsMut, Env};
+use cosmwasm_std::{Addr, Decimal, DepsMut, Env, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::Map;
 
+use crate::contract::utils::{
+    unbonding_cooldown, validate_commission, validate_drift_threshold, validate_early_exit_fee,
+    validate_lock_tiers, validate_ratio, validate_treasury, validate_unlock_penalty,
+    validate_vote_quorum,
+};
 use crate::error::ContractError;
 use crate::msg::MigrateMsg;
-use crate::state::{Config, CONFIG, LATEST_UNBONDING, VALIDATOR_LIST};
+use crate::state::{
+    AllowedAddress, Config, PayoutMode, ALLOWED_ADDRESSES, CONFIG, LATEST_UNBONDING, STAKE_DETAILS,
+    STAKE_SNAPSHOTS, TOTAL, TOTAL_POWER_SNAPSHOT, TOTAL_STAKE_SNAPSHOT, VALIDATOR_LIST,
+};
+
+/// Before 0.4.0 allowed addresses only stored their expiration
+const ALLOWED_ADDRESSES_V0_3: Map<&Addr, Expiration> = Map::new("allowed_addresses");
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -25,20 +37,44 @@ pub fn migrate_config(
     msg: MigrateMsg,
 ) -> Result<(), ContractError> {
     let owner = deps.api.addr_validate(&msg.owner)?;
-    let treasury = deps.api.addr_validate(&msg.treasury)?;
+    let treasury = validate_treasury(deps.api, msg.treasury)?;
 
     let max_entries = msg.max_entries.unwrap_or(7);
-    let unbonding_period = msg.unbonding_period.unwrap_or(3600 * 24 * 28);
-    let min_unbonding_cooldown = Duration::Time(unbonding_period.saturating_div(max_entries));
+    let unbonding_period = Duration::Time(msg.unbonding_period.unwrap_or(3600 * 24 * 28));
+    let min_unbonding_cooldown = unbonding_cooldown(unbonding_period, max_entries)?;
 
     let new_config = Config {
         owner,
         treasury,
-        restake_commission: msg.restake_commission,
-        transfer_commission: msg.transfer_commission,
+        restake_commission: validate_commission(msg.restake_commission)?,
+        transfer_commission: validate_commission(msg.transfer_commission)?,
         denom: msg.denom.clone(),
-        unbonding_period: Duration::Time(unbonding_period),
+        unbonding_period,
         min_unbonding_cooldown,
+        max_entries,
+        drift_threshold: validate_drift_threshold(
+            msg.drift_threshold.unwrap_or(Decimal::percent(5)),
+        )?,
+        referral_commission: validate_ratio(msg.referral_commission.unwrap_or_else(Decimal::zero))?,
+        referral_period: Duration::Time(msg.referral_period.unwrap_or(3600 * 24 * 365)),
+        restake_commission_payout: msg.restake_commission_payout.unwrap_or(PayoutMode::Liquid),
+        transfer_commission_payout: msg.transfer_commission_payout.unwrap_or(PayoutMode::Stake),
+        timelock_delay: Duration::Time(msg.timelock_delay.unwrap_or_default()),
+        vote_quorum: validate_vote_quorum(msg.vote_quorum.unwrap_or(Decimal::percent(10)))?,
+        lock_tiers: validate_lock_tiers(msg.lock_tiers.unwrap_or_default())?,
+        early_unlock_penalty: validate_unlock_penalty(
+            msg.early_unlock_penalty.unwrap_or(Decimal::percent(10)),
+        )?,
+        early_exit_fee: validate_early_exit_fee(msg.early_exit_fee.unwrap_or_default())?,
+        early_exit_blocks: msg.early_exit_blocks.unwrap_or_default(),
+        early_exit_treasury_share: validate_ratio(
+            msg.early_exit_treasury_share
+                .unwrap_or(Decimal::percent(50)),
+        )?,
+        deposit_cap: msg.deposit_cap.unwrap_or_default(),
+        address_cap: msg.address_cap.unwrap_or_default(),
+        min_delegation: msg.min_delegation.unwrap_or_default(),
+        min_undelegation: msg.min_undelegation.unwrap_or_default(),
     };
 
     // sets the latest unbonding period to 4 days from now
@@ -49,5 +85,47 @@ pub fn migrate_config(
 
     VALIDATOR_LIST.save(deps.storage, msg.staking_addr, &Decimal::one())?;
     CONFIG.save(deps.storage, &new_config)?;
+
+    migrate_allowed_addresses(deps.storage)?;
+    seed_stake_snapshots(deps.storage, env.block.height)?;
+    Ok(())
+}
+
+/// Records current stakes as the first snapshot; history before the migration isn't available
+fn seed_stake_snapshots(storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+    let stakes = STAKE_DETAILS
+        .range(storage, None, None, Order::Ascending)
+        .map(|entry| entry.map(|(address, details)| (address, details.staked())))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut power = Uint128::zero();
+    for (address, stake) in stakes {
+        STAKE_SNAPSHOTS.save(storage, &address, &stake, height)?;
+        power += stake;
+    }
+    TOTAL_POWER_SNAPSHOT.save(storage, &power, height)?;
+    if let Some(total) = TOTAL.may_load(storage)? {
+        TOTAL_STAKE_SNAPSHOT.save(storage, &total.amount, height)?;
+    }
+    Ok(())
+}
+
+/// Converts allowed addresses stored as a bare `Expiration`, keeping the former 50/50
+/// commission split without a cap. Entries which are already converted don't parse
+/// as `Expiration` and are left alone.
+fn migrate_allowed_addresses(storage: &mut dyn Storage) -> StdResult<()> {
+    let legacy = ALLOWED_ADDRESSES_V0_3
+        .range(storage, None, None, Order::Ascending)
+        .filter_map(|entry| entry.ok())
+        .collect::<Vec<_>>();
+
+    for (address, expires) in legacy {
+        let allowed = AllowedAddress {
+            expires,
+            commission_ratio: Decimal::percent(50),
+            commission_cap: None,
+        };
+        ALLOWED_ADDRESSES.save(storage, &address, &allowed)?;
+    }
     Ok(())
 }
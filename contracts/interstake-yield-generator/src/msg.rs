@@ -1,15 +1,20 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
+use cosmwasm_std::{Addr, Coin, Decimal, Timestamp, Uint128, VoteOption};
+use cw2::ContractVersion;
 use cw_utils::Expiration;
 
-use crate::state::{ClaimDetails, Config};
+use crate::state::{
+    AdminAction, AllowedAddress, ClaimDetails, CommissionEarnings, Config, ContractStatus, Lock,
+    LockTier, PayoutMode, PendingProposal, RebondDetails, RewardMode, TreasuryCommission,
+    YieldEntry, YieldRecipient,
+};
 
 #[cw_serde]
 pub struct InstantiateMsg {
     /// Multisig contract that is allowed to perform admin operations
     pub owner: String,
-    /// account which receives commissions
-    pub treasury: String,
+    /// accounts which receive commissions, with weights summing to 1
+    pub treasury: Vec<(String, Decimal)>,
     /// Address of validator
     pub staking_addr: String,
     /// Commission for restaking
@@ -22,26 +27,95 @@ pub struct InstantiateMsg {
     pub unbonding_period: Option<u64>,
     /// maxEntries in unbonding queue. Default: 7
     pub max_entries: Option<u64>,
+    /// Allocation drift above which rewards go to underweight validators. Default: 5%
+    pub drift_threshold: Option<Decimal>,
+    /// Share of a referred user's restake commission paid to the referrer. Default: 0
+    pub referral_commission: Option<Decimal>,
+    /// Referral period in seconds. Default: 31_536_000 (365 days)
+    pub referral_period: Option<u64>,
+    /// How restake commission is paid out. Default: liquid
+    pub restake_commission_payout: Option<PayoutMode>,
+    /// How transfer commission is paid out. Default: stake
+    pub transfer_commission_payout: Option<PayoutMode>,
+    /// Delay in seconds before proposed changes can be executed. Default: 0 (no timelock)
+    pub timelock_delay: Option<u64>,
+    /// Share of the total stake which has to vote on a proposal. Default: 10%
+    pub vote_quorum: Option<Decimal>,
+    /// Lock durations with their reward multipliers. Default: none, locking is disabled
+    pub lock_tiers: Option<Vec<LockTier>>,
+    /// Share of a lock forfeited when unlocking early. Default: 10%
+    pub early_unlock_penalty: Option<Decimal>,
+    /// Fee on undelegating stake younger than `early_exit_blocks`. Default: 0
+    pub early_exit_fee: Option<Decimal>,
+    /// Default: 0 (no early exit fee)
+    pub early_exit_blocks: Option<u64>,
+    /// Part of the early exit fee paid to the treasury. Default: 50%
+    pub early_exit_treasury_share: Option<Decimal>,
+    /// Limit of total delegated tokens. Default: 0 (no cap)
+    pub deposit_cap: Option<Uint128>,
+    /// Limit of a single address' stake. Default: 0 (no cap)
+    pub address_cap: Option<Uint128>,
+    /// Default: 0
+    pub min_delegation: Option<Uint128>,
+    /// Default: 0
+    pub min_undelegation: Option<Uint128>,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct ConfigUpdate {
+    pub owner: Option<String>,
+    pub treasury: Option<Vec<(String, Decimal)>>,
+    pub restake_commission: Option<Decimal>,
+    pub transfer_commission: Option<Decimal>,
+    pub unbonding_period: Option<u64>,
+    /// Recomputes the minimum unbonding cooldown together with `unbonding_period`
+    pub max_entries: Option<u64>,
+    pub drift_threshold: Option<Decimal>,
+    pub referral_commission: Option<Decimal>,
+    pub referral_period: Option<u64>,
+    pub restake_commission_payout: Option<PayoutMode>,
+    pub transfer_commission_payout: Option<PayoutMode>,
+    pub timelock_delay: Option<u64>,
+    pub vote_quorum: Option<Decimal>,
+    pub lock_tiers: Option<Vec<LockTier>>,
+    pub early_unlock_penalty: Option<Decimal>,
+    pub early_exit_fee: Option<Decimal>,
+    pub early_exit_blocks: Option<u64>,
+    pub early_exit_treasury_share: Option<Decimal>,
+    /// Zero removes the cap
+    pub deposit_cap: Option<Uint128>,
+    /// Zero removes the cap
+    pub address_cap: Option<Uint128>,
+    pub min_delegation: Option<Uint128>,
+    pub min_undelegation: Option<Uint128>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     /// Only called by owner
-    UpdateConfig {
-        owner: Option<String>,
-        treasury: Option<String>,
-        restake_commission: Option<Decimal>,
-        transfer_commission: Option<Decimal>,
-        unbonding_period: Option<u64>,
-    },
+    UpdateConfig(ConfigUpdate),
     /// Updates the list of validators that will be used for staking
     UpdateValidatorList {
         new_validator_list: Vec<(String, Decimal)>,
     },
-    /// Adds amount of tokens to common staking pool
-    Delegate {},
+    /// Adds amount of tokens to common staking pool; `referrer` has to be an allowed address
+    Delegate {
+        referrer: Option<String>,
+    },
+    /// Delegates sent tokens locked for `duration` seconds, which has to match a lock tier;
+    /// locked stake earns boosted rewards but can't be undelegated or transferred
+    DelegateLocked {
+        duration: u64,
+    },
+    /// Releases a lock; before it expires, the early unlock penalty goes to the treasury
+    Unlock {
+        id: u64,
+    },
     /// Undelegates currently staked portion of token
-    Undelegate { amount: Coin },
+    Undelegate {
+        amount: Coin,
+    },
     /// Transfers to sender any unbonding claims that met their deadline
     Claim {},
     /// Claims rewards and then stake them; Only called by owner
@@ -54,16 +128,126 @@ pub enum ExecuteMsg {
     },
     /// Start unbonding current batch
     BatchUnbond {},
-    /// Undelegates all tokens
-    UndelegateAll {},
+    /// Undelegates all tokens and winds the contract down; Only called by owner.
+    /// Unless `resumable` (default: false), the contract can't be resumed afterwards
+    UndelegateAll {
+        resumable: Option<bool>,
+    },
+    /// Reactivates a resumable contract after `UndelegateAll`; Only called by owner
+    Resume {},
     /// adds (or updates) address to allowed list
     UpdateAllowedAddr {
         address: String,
         /// seconds since epoch
         expires: u64,
+        /// Share of the transfer commission paid to the address. Default: 0.5
+        commission_ratio: Option<Decimal>,
+        /// Maximum cumulative commission the address can earn. Default: no cap
+        commission_cap: Option<Uint128>,
     },
     /// removes address from allowed list
-    RemoveAllowedAddr { address: String },
+    RemoveAllowedAddr {
+        address: String,
+    },
+    /// Delegates tokens which were unbonded in place of a blocked redelegation
+    /// and have been released since
+    Rebond {},
+    /// Redelegates from overweight to underweight validators;
+    /// only allowed once the allocation drift exceeds the threshold
+    Rebalance {},
+    /// Queues stake commission of a treasury recipient for unbonding with the next batch;
+    /// Only called by treasury recipients
+    WithdrawCommission {
+        amount: Uint128,
+    },
+    /// Removes a validator from the list and scales up the weights of the remaining ones;
+    /// Only called by owner
+    RemoveValidator {
+        validator: String,
+        mode: RemovalMode,
+    },
+    /// Stores a config change which can be executed once the timelock delay has passed;
+    /// Only called by owner
+    ProposeConfigChange(ConfigUpdate),
+    /// Stores a validator list change which can be executed once the timelock delay has passed;
+    /// Only called by owner
+    ProposeValidatorList {
+        new_validator_list: Vec<(String, Decimal)>,
+    },
+    /// Applies a pending proposal whose timelock has expired; can be called by anyone
+    ExecutePending {
+        id: u64,
+    },
+    /// Discards a pending proposal; Only called by owner
+    CancelPending {
+        id: u64,
+    },
+    /// Opens a gov proposal for delegator votes until `voting_end`, weighted by the stake at
+    /// the current height; Only called by owner
+    OpenProposal {
+        proposal_id: u64,
+        voting_end: Timestamp,
+    },
+    /// Records the sender's vote on an open gov proposal, weighted by their stake
+    Vote {
+        proposal_id: u64,
+        vote: VoteOption,
+    },
+    /// Casts the stake weighted split of recorded votes on a gov proposal, or the default vote
+    /// if the quorum isn't reached; can be called by anyone
+    CastVote {
+        proposal_id: u64,
+    },
+    /// Sets the vote cast on a gov proposal if delegators don't reach the quorum;
+    /// Only called by owner
+    SetDefaultVote {
+        proposal_id: u64,
+        vote: VoteOption,
+    },
+    /// Registers a contract to be notified about stake changes; Only called by owner
+    AddHook {
+        addr: String,
+    },
+    /// Only called by owner
+    RemoveHook {
+        addr: String,
+    },
+    /// Chooses whether sender's rewards are compounded or paid out
+    SetRewardMode {
+        mode: RewardMode,
+    },
+    /// Sends rewards accrued in liquid mode to sender
+    ClaimRewards {},
+    /// Sends `ratio` of sender's rewards to `recipient`, staked or liquid. Default: stake
+    SetYieldRecipient {
+        recipient: String,
+        ratio: Decimal,
+        payout: Option<PayoutMode>,
+    },
+    ClearYieldRecipient {},
+}
+
+/// Stake of `addr` before and after a change, including partial stakes
+#[cw_serde]
+pub struct StakeChange {
+    pub addr: Addr,
+    pub old: Uint128,
+    pub new: Uint128,
+}
+
+/// Sent to every registered hook whenever a stake changes
+#[cw_serde]
+pub enum StakeChangedHookMsg {
+    StakeChangedHook(StakeChange),
+}
+
+#[cw_serde]
+pub enum RemovalMode {
+    /// Redelegates the stake of the removed validator to the remaining ones right away
+    Redelegate,
+    /// Undelegates the stake of the removed validator; once released, `Rebond {}`
+    /// delegates it to the remaining validators
+    Undelegate,
 }
 
 #[cw_serde]
@@ -81,6 +265,9 @@ pub enum QueryMsg {
     /// Current available reward to claim
     #[returns(RewardResponse)]
     Reward {},
+    /// Current delegation and available reward of every validator in the list
+    #[returns(RewardsByValidatorResponse)]
+    RewardsByValidator {},
     /// Returns the amount of tokens that are currently pending for claim
     #[returns(PendingClaimResponse)]
     PendingClaim { sender: String },
@@ -100,14 +287,102 @@ pub enum QueryMsg {
     /// returns the list of allowed addresses
     #[returns(AllowedAddrListResponse)]
     AllowedAddrList {},
+    /// Returns validators which can't be used as a redelegation source yet,
+    /// together with the time their latest incoming redelegation completes
+    #[returns(BlockedValidatorsResponse)]
+    BlockedValidators {},
+    /// Returns unbonded tokens waiting to be delegated again
+    #[returns(PendingRebondsResponse)]
+    PendingRebonds {},
+    /// Returns how many unbonding entries are still available for each validator
+    #[returns(UnbondingEntriesResponse)]
+    UnbondingEntries {},
+    /// Compares target weights with actual delegations of every validator
+    #[returns(AllocationDriftResponse)]
+    AllocationDrift {},
+    /// Returns the number of referred users and the commission earned by a referrer
+    #[returns(ReferrerStatsResponse)]
+    ReferrerStats { referrer: String },
+    /// Returns commission received by the treasury and, if given, earned by `address`
+    #[returns(CommissionStatsResponse)]
+    CommissionStats { address: Option<String> },
+    /// Returns proposed changes which haven't been executed or cancelled yet
+    #[returns(PendingProposalsResponse)]
+    PendingProposals {},
+    /// Returns stake voting for every option of a gov proposal
+    #[returns(ProposalVotesResponse)]
+    ProposalVotes { proposal_id: u64 },
+    /// Returns whether the contract is active or winding down
+    #[returns(StatusResponse)]
+    Status {},
+    /// Returns logged owner actions, oldest first
+    #[returns(AdminHistoryResponse)]
+    AdminHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns stake of `address` at the beginning of block `height`.
+    /// Defaults to the current stake.
+    #[returns(StakeAtHeightResponse)]
+    StakeAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Returns total stake at the beginning of block `height`.
+    /// Defaults to the current total.
+    #[returns(TotalAtHeightResponse)]
+    TotalAtHeight { height: Option<u64> },
+    /// DAO DAO voting module interface: stake of `address`, including partial stakes
+    #[returns(VotingPowerAtHeightResponse)]
+    VotingPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// DAO DAO voting module interface: sum of all voting power, without queued undelegations
+    #[returns(TotalPowerAtHeightResponse)]
+    TotalPowerAtHeight { height: Option<u64> },
+    /// DAO DAO voting module interface: the DAO this module belongs to, which is the owner
+    #[returns(Addr)]
+    Dao {},
+    /// DAO DAO voting module interface: contract name and version
+    #[returns(InfoResponse)]
+    Info {},
+    /// Returns recorded restakes, oldest first
+    #[returns(YieldHistoryResponse)]
+    YieldHistory {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns APR and APY from restakes in the trailing `window` seconds. Default: 30 days
+    #[returns(CurrentAprResponse)]
+    CurrentApr { window: Option<u64> },
+    /// Returns reward restaked for `address` by restake id, oldest first
+    #[returns(UserEarningsResponse)]
+    UserEarnings {
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns reward mode of `address` and its unclaimed liquid rewards
+    #[returns(LiquidRewardsResponse)]
+    LiquidRewards { address: String },
+    /// Returns locks of `address`, including expired ones which weren't unlocked yet
+    #[returns(LocksResponse)]
+    Locks { address: String },
+    /// Previews undelegating `amount` of the stake of `address`, including the early exit fee
+    #[returns(SimulateUndelegateResponse)]
+    SimulateUndelegate { address: String, amount: Uint128 },
+    /// Returns contracts notified about stake changes
+    #[returns(HooksResponse)]
+    Hooks {},
 }
 
 #[cw_serde]
 pub struct MigrateMsg {
     /// Multisig contract that is allowed to perform admin operations
     pub owner: String,
-    /// account which receives commissions
-    pub treasury: String,
+    /// accounts which receive commissions, with weights summing to 1
+    pub treasury: Vec<(String, Decimal)>,
     /// Address of validator
     pub staking_addr: String,
     /// Commission for restaking
@@ -120,6 +395,38 @@ pub struct MigrateMsg {
     pub unbonding_period: Option<u64>,
     /// maxEntries in unbonding queue. Default: 7
     pub max_entries: Option<u64>,
+    /// Allocation drift above which rewards go to underweight validators. Default: 5%
+    pub drift_threshold: Option<Decimal>,
+    /// Share of a referred user's restake commission paid to the referrer. Default: 0
+    pub referral_commission: Option<Decimal>,
+    /// Referral period in seconds. Default: 31_536_000 (365 days)
+    pub referral_period: Option<u64>,
+    /// How restake commission is paid out. Default: liquid
+    pub restake_commission_payout: Option<PayoutMode>,
+    /// How transfer commission is paid out. Default: stake
+    pub transfer_commission_payout: Option<PayoutMode>,
+    /// Delay in seconds before proposed changes can be executed. Default: 0 (no timelock)
+    pub timelock_delay: Option<u64>,
+    /// Share of the total stake which has to vote on a proposal. Default: 10%
+    pub vote_quorum: Option<Decimal>,
+    /// Lock durations with their reward multipliers. Default: none, locking is disabled
+    pub lock_tiers: Option<Vec<LockTier>>,
+    /// Share of a lock forfeited when unlocking early. Default: 10%
+    pub early_unlock_penalty: Option<Decimal>,
+    /// Fee on undelegating stake younger than `early_exit_blocks`. Default: 0
+    pub early_exit_fee: Option<Decimal>,
+    /// Default: 0 (no early exit fee)
+    pub early_exit_blocks: Option<u64>,
+    /// Part of the early exit fee paid to the treasury. Default: 50%
+    pub early_exit_treasury_share: Option<Decimal>,
+    /// Limit of total delegated tokens. Default: 0 (no cap)
+    pub deposit_cap: Option<Uint128>,
+    /// Limit of a single address' stake. Default: 0 (no cap)
+    pub address_cap: Option<Uint128>,
+    /// Default: 0
+    pub min_delegation: Option<Uint128>,
+    /// Default: 0
+    pub min_undelegation: Option<Uint128>,
 }
 
 #[cw_serde]
@@ -132,6 +439,23 @@ pub struct RewardResponse {
     pub rewards: Vec<Coin>,
 }
 
+#[cw_serde]
+pub struct ValidatorRewards {
+    pub validator: String,
+    /// Target weight from the validator list
+    pub weight: Decimal,
+    pub delegated: Coin,
+    /// Accumulated rewards in all denoms
+    pub rewards: Vec<Coin>,
+    /// Part of total rewards in the staking denom generated by this validator
+    pub share: Decimal,
+}
+
+#[cw_serde]
+pub struct RewardsByValidatorResponse {
+    pub validators: Vec<ValidatorRewards>,
+}
+
 #[cw_serde]
 pub struct PendingClaimResponse {
     pub amount: Uint128,
@@ -145,6 +469,7 @@ pub struct ClaimsResponse {
 #[cw_serde]
 pub struct DelegatedResponse {
     pub delegated: Vec<DelegateResponse>,
+    pub yield_recipient: Option<YieldRecipient>,
 }
 
 #[cw_serde]
@@ -177,9 +502,156 @@ pub struct ValidatorWeightResponse {
 #[cw_serde]
 pub struct AllowedAddrResponse {
     pub expires: Expiration,
+    pub commission_ratio: Decimal,
+    pub commission_cap: Option<Uint128>,
+    pub commission_earned: Uint128,
 }
 
 #[cw_serde]
 pub struct AllowedAddrListResponse {
-    pub allowed_list: Vec<(Addr, Expiration)>,
+    pub allowed_list: Vec<(Addr, AllowedAddress)>,
+}
+
+#[cw_serde]
+pub struct ReferrerStatsResponse {
+    pub referred_users: u64,
+    pub commission_earned: Uint128,
+}
+
+#[cw_serde]
+pub struct CommissionStatsResponse {
+    pub treasury: TreasuryCommission,
+    /// Transfer commission earned by the requested address
+    pub address: Option<CommissionEarnings>,
+    /// Restake commission earned by the requested address as a referrer
+    pub referral_commission: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct BlockedValidatorsResponse {
+    pub validators: Vec<(String, Expiration)>,
+}
+
+#[cw_serde]
+pub struct ProposalVotesResponse {
+    pub tally: Vec<(VoteOption, Uint128)>,
+    /// Share of the total stake which voted
+    pub participation: Decimal,
+    pub default_vote: Option<VoteOption>,
+}
+
+#[cw_serde]
+pub struct StatusResponse {
+    pub status: ContractStatus,
+}
+
+#[cw_serde]
+pub struct AdminHistoryResponse {
+    pub entries: Vec<(u64, AdminAction)>,
+}
+
+#[cw_serde]
+pub struct StakeAtHeightResponse {
+    pub stake: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct TotalAtHeightResponse {
+    pub total: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct VotingPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct TotalPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[cw_serde]
+pub struct InfoResponse {
+    pub info: ContractVersion,
+}
+
+#[cw_serde]
+pub struct YieldHistoryResponse {
+    pub entries: Vec<(u64, YieldEntry)>,
+}
+
+#[cw_serde]
+pub struct CurrentAprResponse {
+    pub apr: Decimal,
+    pub apy: Decimal,
+    /// Restakes the rates are based on
+    pub restakes: u32,
+    pub window: u64,
+}
+
+#[cw_serde]
+pub struct UserEarningsResponse {
+    pub earnings: Vec<(u64, Uint128)>,
+}
+
+#[cw_serde]
+pub struct LiquidRewardsResponse {
+    pub mode: RewardMode,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct LocksResponse {
+    pub locks: Vec<(u64, Lock)>,
+}
+
+#[cw_serde]
+pub struct SimulateUndelegateResponse {
+    pub amount: Uint128,
+    pub fee: Uint128,
+    /// Amount which ends up in the pending claim
+    pub received: Uint128,
+    /// First height at which undelegating is free of the early exit fee
+    pub fee_free_height: u64,
+}
+
+#[cw_serde]
+pub struct HooksResponse {
+    pub hooks: Vec<String>,
+}
+
+#[cw_serde]
+pub struct PendingProposalsResponse {
+    pub proposals: Vec<(u64, PendingProposal)>,
+}
+
+#[cw_serde]
+pub struct PendingRebondsResponse {
+    pub rebonds: Vec<RebondDetails>,
+}
+
+#[cw_serde]
+pub struct ValidatorDrift {
+    pub validator: String,
+    pub target_weight: Decimal,
+    pub delegated: Uint128,
+    pub actual_share: Decimal,
+    /// Absolute difference between target weight and actual share
+    pub drift: Decimal,
+}
+
+#[cw_serde]
+pub struct AllocationDriftResponse {
+    pub validators: Vec<ValidatorDrift>,
+    pub max_drift: Decimal,
+    pub threshold: Decimal,
+}
+
+#[cw_serde]
+pub struct UnbondingEntriesResponse {
+    pub entries: Vec<(String, u64)>,
 }
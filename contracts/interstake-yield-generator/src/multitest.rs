@@ -1,6 +1,9 @@
 mod config;
 mod delegate;
+mod governance;
+mod lock;
 mod migrate;
+mod redelegate;
 mod suite;
 mod transfer;
 mod undelegate;
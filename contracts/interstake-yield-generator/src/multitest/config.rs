@@ -1,12 +1,13 @@
-use super::suite::{SuiteBuilder, TWENTY_EIGHT_DAYS};
+use super::suite::{SuiteBuilder, FOUR_DAYS, ONE_YEAR, TWENTY_EIGHT_DAYS};
 
-use cosmwasm_std::{coin, Addr, Decimal, StakingMsg, Uint128};
+use cosmwasm_std::{coin, from_slice, Addr, Decimal, StakingMsg, Uint128};
 use cw_utils::Duration;
 
 use crate::contract::utils::compute_redelegate_msgs;
 use crate::error::ContractError;
-use crate::multitest::suite::{two_false_validators, validator_list};
-use crate::state::Config;
+use crate::msg::{ConfigUpdate, RemovalMode};
+use crate::multitest::suite::{two_false_validators, validator_list, VALIDATOR_1};
+use crate::state::{Config, PayoutMode, PendingChange};
 
 #[test]
 fn update_not_owner() {
@@ -23,7 +24,7 @@ fn proper_update() {
     let mut suite = SuiteBuilder::new().build();
 
     let owner = suite.owner();
-    let treasury = suite.treasury();
+    let treasury = vec![(suite.treasury(), Decimal::one())];
     assert_eq!(
         suite.query_config().unwrap(),
         Config {
@@ -34,6 +35,23 @@ fn proper_update() {
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(TWENTY_EIGHT_DAYS),
             min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -51,6 +69,23 @@ fn proper_update() {
             unbonding_period: Duration::Time(TWENTY_EIGHT_DAYS),
 
             min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -68,6 +103,23 @@ fn proper_update() {
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(TWENTY_EIGHT_DAYS),
             min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -84,24 +136,58 @@ fn proper_update() {
             transfer_commission: Decimal::zero(),
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(new_unbonding_period),
-            min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            min_unbonding_cooldown: Duration::Time(new_unbonding_period / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
-    let new_treasury = "new_treasury".to_owned();
+    let new_treasury = vec![("new_treasury".to_owned(), Decimal::one())];
     suite
-        .update_config(owner.as_str(), None, new_treasury.clone(), None, None, None)
+        .update_config(owner.as_str(), None, new_treasury, None, None, None)
         .unwrap();
     assert_eq!(
         suite.query_config().unwrap(),
         Config {
             owner: owner.clone(),
-            treasury: Addr::unchecked(new_treasury.clone()),
+            treasury: vec![(Addr::unchecked("new_treasury"), Decimal::one())],
             restake_commission: new_team_commission,
             transfer_commission: Decimal::zero(),
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(new_unbonding_period),
-            min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            min_unbonding_cooldown: Duration::Time(new_unbonding_period / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -120,12 +206,29 @@ fn proper_update() {
         suite.query_config().unwrap(),
         Config {
             owner: owner.clone(),
-            treasury: Addr::unchecked(new_treasury.clone()),
+            treasury: vec![(Addr::unchecked("new_treasury"), Decimal::one())],
             restake_commission: new_team_commission,
             transfer_commission: new_transfer_commission,
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(new_unbonding_period),
-            min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            min_unbonding_cooldown: Duration::Time(new_unbonding_period / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -137,12 +240,29 @@ fn proper_update() {
         suite.query_config().unwrap(),
         Config {
             owner: Addr::unchecked(new_owner),
-            treasury: Addr::unchecked(new_treasury),
+            treasury: vec![(Addr::unchecked("new_treasury"), Decimal::one())],
             restake_commission: new_team_commission,
             transfer_commission: new_transfer_commission,
             denom: "ujuno".to_owned(),
             unbonding_period: Duration::Time(new_unbonding_period),
-            min_unbonding_cooldown: Duration::Time(TWENTY_EIGHT_DAYS / 7),
+            min_unbonding_cooldown: Duration::Time(new_unbonding_period / 7),
+            max_entries: 7,
+            drift_threshold: Decimal::percent(5),
+            referral_commission: Decimal::zero(),
+            referral_period: Duration::Time(ONE_YEAR),
+            restake_commission_payout: PayoutMode::Liquid,
+            transfer_commission_payout: PayoutMode::Stake,
+            timelock_delay: Duration::Time(0),
+            vote_quorum: Decimal::percent(10),
+            lock_tiers: vec![],
+            early_unlock_penalty: Decimal::percent(10),
+            early_exit_fee: Decimal::zero(),
+            early_exit_blocks: 0,
+            early_exit_treasury_share: Decimal::percent(50),
+            deposit_cap: Uint128::zero(),
+            address_cap: Uint128::zero(),
+            min_delegation: Uint128::zero(),
+            min_undelegation: Uint128::zero(),
         }
     );
 
@@ -153,6 +273,400 @@ fn proper_update() {
     assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
 }
 
+#[test]
+fn invalid_treasury() {
+    let mut suite = SuiteBuilder::new().build();
+    let owner = suite.owner();
+
+    let err = suite
+        .update_config(
+            owner.as_str(),
+            None,
+            vec![
+                ("team".to_owned(), Decimal::percent(60)),
+                ("ops".to_owned(), Decimal::percent(30)),
+            ],
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidTreasury {}, err.downcast().unwrap());
+
+    let err = suite
+        .update_config(owner.as_str(), None, vec![], None, None, None)
+        .unwrap_err();
+    assert_eq!(ContractError::InvalidTreasury {}, err.downcast().unwrap());
+}
+
+#[test]
+fn commission_bounds() {
+    let mut suite = SuiteBuilder::new().build();
+    let owner = suite.owner();
+
+    let err = suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(11), None, None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CommissionChangeTooLarge {
+            old: Decimal::zero(),
+            new: Decimal::percent(11),
+            max_change: Decimal::percent(10),
+        },
+        err.downcast().unwrap()
+    );
+
+    let err = suite
+        .update_config(
+            owner.as_str(),
+            None,
+            None,
+            None,
+            Decimal::percent(101),
+            None,
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CommissionTooHigh {
+            commission: Decimal::percent(101),
+            max: Decimal::percent(50),
+        },
+        err.downcast().unwrap()
+    );
+
+    suite
+        .update_config(
+            owner.as_str(),
+            None,
+            None,
+            Decimal::percent(10),
+            Decimal::percent(10),
+            None,
+        )
+        .unwrap();
+
+    // the cap applies to the whole change window, so a second step can't add to the first
+    let err = suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(20), None, None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CommissionChangeTooLarge {
+            old: Decimal::zero(),
+            new: Decimal::percent(20),
+            max_change: Decimal::percent(10),
+        },
+        err.downcast().unwrap()
+    );
+    suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(5), None, None)
+        .unwrap();
+
+    // ~1 day later a new window starts from the current commission
+    suite.advance_height(14_400);
+    suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(15), None, None)
+        .unwrap();
+    let config = suite.query_config().unwrap();
+    assert_eq!(config.restake_commission, Decimal::percent(15));
+    assert_eq!(config.transfer_commission, Decimal::percent(10));
+}
+
+#[test]
+fn drift_threshold_and_referral_bounds() {
+    let mut suite = SuiteBuilder::new().build();
+    let owner = suite.owner();
+
+    for threshold in [Decimal::zero(), Decimal::percent(101)] {
+        let err = suite
+            .update_config_with(
+                owner.as_str(),
+                ConfigUpdate {
+                    drift_threshold: Some(threshold),
+                    ..ConfigUpdate::default()
+                },
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::InvalidDriftThreshold { threshold },
+            err.downcast().unwrap()
+        );
+    }
+
+    let err = suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                referral_commission: Some(Decimal::percent(101)),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidCommissionRatio {
+            ratio: Decimal::percent(101),
+        },
+        err.downcast().unwrap()
+    );
+
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                drift_threshold: Some(Decimal::one()),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        suite.query_config().unwrap().drift_threshold,
+        Decimal::one()
+    );
+}
+
+#[test]
+fn unbonding_period_and_max_entries() {
+    let mut suite = SuiteBuilder::new().build();
+    let owner = suite.owner();
+
+    let err = suite
+        .update_config(owner.as_str(), None, None, None, None, 0)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ZeroUnbondingPeriod {},
+        err.downcast().unwrap()
+    );
+
+    let err = suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                max_entries: Some(0),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap_err();
+    assert_eq!(ContractError::ZeroMaxEntries {}, err.downcast().unwrap());
+
+    // cooldown follows the stored unbonding period when only max entries change
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                max_entries: Some(4),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+    let config = suite.query_config().unwrap();
+    assert_eq!(config.max_entries, 4);
+    assert_eq!(
+        config.min_unbonding_cooldown,
+        Duration::Time(TWENTY_EIGHT_DAYS / 4)
+    );
+}
+
+#[test]
+fn timelocked_config_change() {
+    let mut suite = SuiteBuilder::new().with_timelock_delay(FOUR_DAYS).build();
+    let owner = suite.owner();
+
+    let err = suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(5), None, None)
+        .unwrap_err();
+    assert_eq!(ContractError::TimelockActive {}, err.downcast().unwrap());
+
+    let update = ConfigUpdate {
+        transfer_commission: Some(Decimal::percent(5)),
+        ..ConfigUpdate::default()
+    };
+    let err = suite
+        .propose_config_change("random_user", update.clone())
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    // invalid changes are rejected when proposed
+    let err = suite
+        .propose_config_change(
+            owner.as_str(),
+            ConfigUpdate {
+                transfer_commission: Some(Decimal::percent(60)),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CommissionTooHigh {
+            commission: Decimal::percent(60),
+            max: Decimal::percent(50),
+        },
+        err.downcast().unwrap()
+    );
+
+    suite
+        .propose_config_change(owner.as_str(), update.clone())
+        .unwrap();
+    let proposals = suite.query_pending_proposals().unwrap();
+    assert_eq!(proposals.len(), 1);
+    let (id, proposal) = &proposals[0];
+    assert_eq!(*id, 1);
+    assert_eq!(proposal.proposer, owner);
+    assert_eq!(proposal.change, PendingChange::Config(update));
+
+    suite.advance_time(FOUR_DAYS - 1);
+    let err = suite.execute_pending("random_user", 1).unwrap_err();
+    assert_eq!(
+        ContractError::TimelockNotExpired {
+            execute_after: proposal.execute_after,
+        },
+        err.downcast().unwrap()
+    );
+
+    // anyone can execute once the delay has passed
+    suite.advance_time(1);
+    suite.execute_pending("random_user", 1).unwrap();
+    assert_eq!(
+        suite.query_config().unwrap().transfer_commission,
+        Decimal::percent(5)
+    );
+    assert_eq!(suite.query_pending_proposals().unwrap(), vec![]);
+
+    // the change is attributed to the owner who proposed it, not to whoever executed it
+    let history = suite.query_admin_history(None, None).unwrap();
+    let (_, executed) = history.last().unwrap();
+    assert_eq!(executed.action, "update_config");
+    assert_eq!(executed.sender, owner);
+    assert_eq!(executed.executor, Some(Addr::unchecked("random_user")));
+
+    let err = suite.execute_pending("random_user", 1).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalNotFound { id: 1 },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn timelocked_validator_list() {
+    let mut suite = SuiteBuilder::new().with_timelock_delay(FOUR_DAYS).build();
+    let owner = suite.owner();
+
+    let err = suite
+        .update_validator_list(owner.as_str(), validator_list(2))
+        .unwrap_err();
+    assert_eq!(ContractError::TimelockActive {}, err.downcast().unwrap());
+
+    let err = suite
+        .propose_validator_list(owner.as_str(), two_false_validators())
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidValidatorList {},
+        err.downcast().unwrap()
+    );
+
+    suite
+        .propose_validator_list(owner.as_str(), validator_list(2))
+        .unwrap();
+    suite
+        .propose_validator_list(owner.as_str(), validator_list(1))
+        .unwrap();
+
+    let err = suite.cancel_pending("random_user", 1).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    suite.cancel_pending(owner.as_str(), 1).unwrap();
+
+    let proposals = suite.query_pending_proposals().unwrap();
+    assert_eq!(proposals.len(), 1);
+    assert_eq!(proposals[0].0, 2);
+
+    suite.advance_time(FOUR_DAYS);
+    let err = suite.execute_pending("random_user", 1).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalNotFound { id: 1 },
+        err.downcast().unwrap()
+    );
+    suite.execute_pending("random_user", 2).unwrap();
+    assert_eq!(suite.query_validator_list().unwrap(), validator_list(1));
+}
+
+#[test]
+fn timelocked_validator_removal() {
+    let mut suite = SuiteBuilder::new().with_timelock_delay(FOUR_DAYS).build();
+    let owner = suite.owner();
+    let validators = suite.query_validator_list().unwrap();
+
+    let err = suite
+        .remove_validator(owner.as_str(), VALIDATOR_1, RemovalMode::Redelegate)
+        .unwrap_err();
+    assert_eq!(ContractError::TimelockActive {}, err.downcast().unwrap());
+    assert_eq!(suite.query_validator_list().unwrap(), validators);
+}
+
+#[test]
+fn admin_history() {
+    let mut suite = SuiteBuilder::new().build();
+    let owner = suite.owner();
+    assert_eq!(suite.query_admin_history(None, None).unwrap(), vec![]);
+
+    suite
+        .update_config(owner.as_str(), None, None, Decimal::percent(5), None, None)
+        .unwrap();
+    suite
+        .update_validator_list(owner.as_str(), validator_list(2))
+        .unwrap();
+    let expires = suite
+        .app
+        .block_info()
+        .time
+        .plus_seconds(TWENTY_EIGHT_DAYS + 1)
+        .seconds();
+    suite
+        .update_allowed_addr(owner.as_str(), "allowed", expires)
+        .unwrap();
+    suite.advance_height(10);
+    suite
+        .remove_allowed_addr(owner.as_str(), "allowed")
+        .unwrap();
+
+    // failed actions are not logged
+    suite
+        .update_config("random_user", None, None, None, None, None)
+        .unwrap_err();
+
+    let history = suite.query_admin_history(None, None).unwrap();
+    let actions: Vec<_> = history
+        .iter()
+        .map(|(id, entry)| (*id, entry.action.as_str()))
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            (1, "update_config"),
+            (2, "update_validator_list"),
+            (3, "update_allowed_address"),
+            (4, "remove_allowed_address"),
+        ]
+    );
+    assert!(history
+        .iter()
+        .all(|(_, entry)| entry.sender == owner && entry.executor.is_none()));
+
+    let (_, config_change) = &history[0];
+    let old_config: Config =
+        from_slice(config_change.old_value.as_ref().unwrap().as_bytes()).unwrap();
+    let new_config: Config =
+        from_slice(config_change.new_value.as_ref().unwrap().as_bytes()).unwrap();
+    assert_eq!(old_config.restake_commission, Decimal::zero());
+    assert_eq!(new_config.restake_commission, Decimal::percent(5));
+
+    let (_, removal) = &history[3];
+    assert_eq!(removal.height, history[2].1.height + 10);
+    assert!(removal.old_value.is_some());
+    assert_eq!(removal.new_value, None);
+
+    let page = suite.query_admin_history(2, 1).unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].0, 3);
+}
+
 #[test]
 fn update_validator_list() {
     let mut suite = SuiteBuilder::new().build();
@@ -1,10 +1,13 @@
-use super::suite::{SuiteBuilder, TWENTY_EIGHT_DAYS};
+use super::suite::{SuiteBuilder, ONE_YEAR, TWENTY_EIGHT_DAYS};
 
 use crate::{
-    msg::{DelegateResponse, TotalDelegatedResponse},
+    error::ContractError,
+    msg::{ConfigUpdate, DelegateResponse, TotalDelegatedResponse},
     multitest::suite::validator_list,
+    state::{PayoutMode, RewardMode, YieldRecipient},
 };
-use cosmwasm_std::{assert_approx_eq, coin, coins, Decimal, Uint128};
+use cosmwasm_std::{assert_approx_eq, coin, coins, Addr, Decimal, Uint128};
+use cw_multi_test::AppResponse;
 use test_case::test_case;
 
 const ONE_DAY: u64 = 3600 * 24;
@@ -558,6 +561,37 @@ fn partial_user_become_full_after_restake(i: u32) {
     );
 }
 
+#[test]
+fn rewards_by_validator() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(100_000_000, "ujuno"))
+        .build();
+    suite
+        .update_validator_list(suite.owner().as_str(), validator_list(2))
+        .unwrap();
+
+    suite.delegate(user, coin(100_000_000, "ujuno")).unwrap();
+    suite.advance_time(ONE_DAY);
+
+    let validators = suite.query_rewards_by_validator().unwrap();
+    assert_eq!(validators.len(), 2);
+    for (validator, (expected_validator, weight)) in validators.iter().zip(validator_list(2)) {
+        assert_eq!(validator.validator, expected_validator);
+        assert_eq!(validator.weight, weight);
+        assert_eq!(validator.delegated, coin(50_000_000, "ujuno"));
+        assert_eq!(validator.rewards.len(), 1);
+        assert_eq!(validator.share, Decimal::percent(50));
+    }
+
+    // breakdown sums up to the total reward
+    let total_reward: Uint128 = validators
+        .iter()
+        .map(|validator| validator.rewards[0].amount)
+        .sum();
+    assert_eq!(total_reward, suite.query_reward().unwrap().amount);
+}
+
 #[test]
 fn redelegate_after_validator_list_update() {
     let validators = validator_list(2);
@@ -591,3 +625,577 @@ fn redelegate_after_validator_list_update() {
             .unwrap()
     );
 }
+
+#[test]
+fn delegate_with_referrer() {
+    let user = "user";
+    let partner = "partner";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(100_000_000, "ujuno"))
+        .with_restake_commission(Decimal::percent(10))
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                referral_commission: Some(Decimal::percent(50)),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+
+    let err = suite
+        .delegate_with_referrer(user, coin(50_000_000, "ujuno"), partner)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CommissionAddressNotFound {
+            address: partner.to_owned()
+        },
+        err.downcast().unwrap()
+    );
+
+    let err = suite
+        .delegate_with_referrer(user, coin(50_000_000, "ujuno"), user)
+        .unwrap_err();
+    assert_eq!(ContractError::SelfReferral {}, err.downcast().unwrap());
+
+    let expires = suite
+        .app
+        .block_info()
+        .time
+        .plus_seconds(TWENTY_EIGHT_DAYS + 1)
+        .seconds();
+    suite
+        .update_allowed_addr(owner.as_str(), partner, expires)
+        .unwrap();
+
+    suite
+        .delegate_with_referrer(user, coin(50_000_000, "ujuno"), partner)
+        .unwrap();
+    // delegating again doesn't count as another referred user
+    suite
+        .delegate_with_referrer(user, coin(50_000_000, "ujuno"), partner)
+        .unwrap();
+    assert_eq!(
+        suite.query_referrer_stats(partner).unwrap().referred_users,
+        1
+    );
+
+    suite.advance_height(500);
+    let commission = Decimal::percent(10) * suite.query_reward().unwrap().amount;
+    suite.restake(owner.as_str()).unwrap();
+
+    // the user is the only staker, so half of the whole commission goes to the partner
+    let payout = Decimal::percent(50) * commission;
+    assert_eq!(
+        suite
+            .query_referrer_stats(partner)
+            .unwrap()
+            .commission_earned,
+        payout
+    );
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(partner, "ujuno")
+            .unwrap()
+            .amount,
+        payout
+    );
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(suite.treasury(), "ujuno")
+            .unwrap()
+            .amount,
+        commission - payout
+    );
+
+    // referrals end after the referral period
+    suite.advance_time(ONE_YEAR);
+    suite.restake(owner.as_str()).unwrap();
+    assert_eq!(
+        suite
+            .query_referrer_stats(partner)
+            .unwrap()
+            .commission_earned,
+        payout
+    );
+}
+
+#[test]
+fn stake_snapshots_by_height() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(500, "ujuno"))
+        .with_funds(user2, &coins(300, "ujuno"))
+        .build();
+
+    let start = suite.block_height();
+    suite.delegate(user1, coin(200, "ujuno")).unwrap();
+    suite.advance_height(10);
+    let second = suite.block_height();
+    suite.delegate(user1, coin(300, "ujuno")).unwrap();
+    suite.delegate(user2, coin(300, "ujuno")).unwrap();
+    suite.advance_height(10);
+
+    // snapshot at a height reflects the state at the beginning of that block
+    assert_eq!(
+        suite.query_stake_at_height(user1, start).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        suite.query_stake_at_height(user1, start + 1).unwrap(),
+        Uint128::new(200)
+    );
+    assert_eq!(
+        suite.query_stake_at_height(user1, second).unwrap(),
+        Uint128::new(200)
+    );
+    assert_eq!(
+        suite.query_stake_at_height(user1, second + 1).unwrap(),
+        Uint128::new(500)
+    );
+    assert_eq!(
+        suite.query_stake_at_height(user2, second).unwrap(),
+        Uint128::zero()
+    );
+    assert_eq!(
+        suite.query_stake_at_height(user2, None).unwrap(),
+        Uint128::new(300)
+    );
+
+    assert_eq!(suite.query_total_at_height(start).unwrap(), Uint128::zero());
+    assert_eq!(
+        suite.query_total_at_height(second).unwrap(),
+        Uint128::new(200)
+    );
+    assert_eq!(
+        suite.query_total_at_height(None).unwrap(),
+        Uint128::new(800)
+    );
+}
+
+/// Stake changes reported by `hook` as (addr, old, new)
+fn hook_changes(response: &AppResponse, hook: &Addr) -> Vec<(String, String, String)> {
+    response
+        .events
+        .iter()
+        .filter(|event| {
+            event.ty == "wasm"
+                && event
+                    .attributes
+                    .iter()
+                    .any(|attr| attr.key == "_contract_addr" && attr.value == hook.as_str())
+        })
+        .map(|event| {
+            let value = |key: &str| {
+                event
+                    .attributes
+                    .iter()
+                    .find(|attr| attr.key == key)
+                    .map(|attr| attr.value.clone())
+                    .unwrap_or_default()
+            };
+            (value("addr"), value("old"), value("new"))
+        })
+        .collect()
+}
+
+#[test]
+fn stake_changed_hooks() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(500, "ujuno"))
+        .build();
+    let owner = suite.owner();
+    let hook = suite.instantiate_hook_receiver();
+
+    let err = suite.add_hook(user, &hook).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    suite.add_hook(owner.as_str(), &hook).unwrap();
+    let err = suite.add_hook(owner.as_str(), &hook).unwrap_err();
+    assert_eq!(
+        ContractError::HookAlreadyRegistered {
+            addr: hook.to_string()
+        },
+        err.downcast().unwrap()
+    );
+    assert_eq!(suite.query_hooks().unwrap(), vec![hook.to_string()]);
+
+    let response = suite.delegate(user, coin(200, "ujuno")).unwrap();
+    assert_eq!(
+        hook_changes(&response, &hook),
+        vec![(user.to_owned(), "0".to_owned(), "200".to_owned())]
+    );
+    let response = suite.delegate(user, coin(300, "ujuno")).unwrap();
+    assert_eq!(
+        hook_changes(&response, &hook),
+        vec![(user.to_owned(), "200".to_owned(), "500".to_owned())]
+    );
+
+    suite.remove_hook(owner.as_str(), &hook).unwrap();
+    assert_eq!(suite.query_hooks().unwrap(), Vec::<String>::new());
+    let err = suite.remove_hook(owner.as_str(), &hook).unwrap_err();
+    assert_eq!(
+        ContractError::HookNotRegistered {
+            addr: hook.to_string()
+        },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn yield_history_and_apr() {
+    let user = "user";
+    let delegated = Uint128::new(100_000_000u128);
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(delegated.u128(), "ujuno"))
+        .with_restake_commission(Decimal::percent(10))
+        .build();
+    let owner = suite.owner();
+
+    // nothing restaked yet
+    let apr = suite.query_current_apr(None).unwrap();
+    assert_eq!(apr.apr, Decimal::zero());
+    assert_eq!(apr.restakes, 0);
+
+    suite
+        .delegate(user, coin(delegated.u128(), "ujuno"))
+        .unwrap();
+    for _ in 0..3 {
+        suite.advance_time(ONE_DAY);
+        suite.restake(owner.as_str()).unwrap();
+    }
+
+    let history = suite.query_yield_history(None, None).unwrap();
+    assert_eq!(history.len(), 3);
+    let (id, first) = &history[0];
+    assert_eq!(*id, 1);
+    assert_eq!(first.total_before, delegated);
+    assert_eq!(first.total_after, first.total_before + first.reward);
+    assert_eq!(first.blocks, ONE_DAY / 5);
+    // 10% of the gross reward is taken as commission
+    assert_approx_eq!(first.commission.u128() * 9, first.reward.u128(), "0.0001");
+    let (_, second) = &history[1];
+    assert_eq!(second.total_before, first.total_after);
+    assert_eq!(second.time, first.time.plus_seconds(ONE_DAY));
+
+    let page = suite.query_yield_history(1, 1).unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].0, 2);
+
+    // Validator APR is 80% with 5% commission, then 10% restake commission
+    // 0.8 * 0.95 * 0.9 = 0.684
+    let apr = suite.query_current_apr(None).unwrap();
+    assert_eq!(apr.restakes, 2);
+    assert_approx_eq!(
+        (apr.apr * Uint128::new(1_000_000)).u128(),
+        684_000u128,
+        "0.001"
+    );
+    assert!(apr.apy > apr.apr);
+
+    // the window only covers the latest restake, which doesn't span a period
+    let apr = suite.query_current_apr(ONE_DAY / 2).unwrap();
+    assert_eq!(apr.restakes, 0);
+}
+
+#[test]
+fn user_earnings_by_restake() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(3_000_000, "ujuno"))
+        .with_funds(user2, &coins(1_000_000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user1, coin(3_000_000, "ujuno")).unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    let first = suite.query_delegated(user1).unwrap().total_earnings;
+
+    // user2 joins for the second restake only
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    let total = suite.query_delegated(user1).unwrap().total_earnings;
+
+    let earnings = suite.query_user_earnings(user1, None, None).unwrap();
+    assert_eq!(earnings, vec![(1, first), (2, total - first)]);
+
+    let earnings = suite.query_user_earnings(user2, None, None).unwrap();
+    assert_eq!(
+        earnings,
+        vec![(2, suite.query_delegated(user2).unwrap().total_earnings)]
+    );
+
+    let page = suite.query_user_earnings(user1, 1, 1).unwrap();
+    assert_eq!(page, vec![(2, total - first)]);
+}
+
+#[test]
+fn liquid_reward_mode() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(1_000_000, "ujuno"))
+        .with_funds(user2, &coins(1_000_000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user1, coin(1_000_000, "ujuno")).unwrap();
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+    suite.set_reward_mode(user2, RewardMode::Liquid).unwrap();
+
+    let err = suite.claim_rewards(user2).unwrap_err();
+    assert_eq!(ContractError::NoLiquidRewards {}, err.downcast().unwrap());
+
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    // user1 compounds, user2 keeps the stake and accrues tokens
+    let compounded = suite.query_delegated(user1).unwrap();
+    let liquid = suite.query_delegated(user2).unwrap();
+    assert_eq!(liquid.total_staked, Uint128::new(1_000_000));
+    assert_eq!(liquid.total_earnings, compounded.total_earnings);
+    assert_eq!(
+        compounded.total_staked,
+        Uint128::new(1_000_000) + compounded.total_earnings
+    );
+
+    let rewards = suite.query_liquid_rewards(user2).unwrap();
+    assert_eq!(rewards.mode, RewardMode::Liquid);
+    assert_eq!(rewards.amount, liquid.total_earnings);
+    // only the compounded part is delegated
+    assert_eq!(
+        suite.query_total_delegated().unwrap().amount.amount,
+        compounded.total_staked + liquid.total_staked
+    );
+
+    suite.claim_rewards(user2).unwrap();
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(user2, "ujuno")
+            .unwrap()
+            .amount,
+        rewards.amount
+    );
+    assert_eq!(
+        suite.query_liquid_rewards(user2).unwrap().amount,
+        Uint128::zero()
+    );
+
+    // back to compounding
+    suite.set_reward_mode(user2, RewardMode::Compound).unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    assert!(suite.query_delegated(user2).unwrap().total_staked > Uint128::new(1_000_000));
+    assert_eq!(
+        suite.query_liquid_rewards(user2).unwrap().amount,
+        Uint128::zero()
+    );
+}
+
+#[test]
+fn yield_recipient() {
+    let (user, charity, fund) = ("user", "charity", "fund");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1_000_000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    let err = suite
+        .set_yield_recipient(user, user, Decimal::percent(50), None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::SelfYieldRecipient {},
+        err.downcast().unwrap()
+    );
+    let err = suite
+        .set_yield_recipient(user, charity, Decimal::percent(150), None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidYieldRatio {
+            ratio: Decimal::percent(150)
+        },
+        err.downcast().unwrap()
+    );
+
+    suite.delegate(user, coin(1_000_000, "ujuno")).unwrap();
+    suite
+        .set_yield_recipient(user, charity, Decimal::percent(50), None)
+        .unwrap();
+    assert_eq!(
+        suite.query_yield_recipient(user).unwrap(),
+        Some(YieldRecipient {
+            recipient: Addr::unchecked(charity),
+            ratio: Decimal::percent(50),
+            payout: PayoutMode::Stake,
+        })
+    );
+
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    // half of the yield is staked for the charity, the principal stays
+    let kept = suite.query_delegated(user).unwrap().total_earnings;
+    let redirected = suite.query_delegated(charity).unwrap().total_staked;
+    assert!(!redirected.is_zero());
+    assert_approx_eq!(kept.u128(), redirected.u128(), "0.0001");
+    assert_eq!(
+        suite.query_total_delegated().unwrap().amount.amount,
+        Uint128::new(1_000_000) + kept + redirected
+    );
+
+    // the whole yield paid out liquid
+    suite
+        .set_yield_recipient(user, fund, Decimal::one(), PayoutMode::Liquid)
+        .unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    assert_eq!(suite.query_delegated(user).unwrap().total_earnings, kept);
+    assert!(!suite
+        .app
+        .wrap()
+        .query_balance(fund, "ujuno")
+        .unwrap()
+        .amount
+        .is_zero());
+
+    suite.clear_yield_recipient(user).unwrap();
+    assert_eq!(suite.query_yield_recipient(user).unwrap(), None);
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    assert!(suite.query_delegated(user).unwrap().total_earnings > kept);
+}
+
+#[test]
+fn deposit_caps_and_minimums() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(1_000_000, "ujuno"))
+        .with_funds(user2, &coins(1_000_000, "ujuno"))
+        .with_deposit_caps(1_000_000, 600_000)
+        .with_minimums(1_000, 500)
+        .build();
+    let owner = suite.owner();
+
+    let err = suite.delegate(user1, coin(999, "ujuno")).unwrap_err();
+    assert_eq!(
+        ContractError::DelegationTooSmall {
+            min: Uint128::new(1_000),
+            amount: Uint128::new(999)
+        },
+        err.downcast().unwrap()
+    );
+
+    suite.delegate(user1, coin(500_000, "ujuno")).unwrap();
+    let err = suite.delegate(user1, coin(200_000, "ujuno")).unwrap_err();
+    assert_eq!(
+        ContractError::AddressCapExceeded {
+            cap: Uint128::new(600_000),
+            staked: Uint128::new(700_000)
+        },
+        err.downcast().unwrap()
+    );
+
+    suite.delegate(user2, coin(500_000, "ujuno")).unwrap();
+    let err = suite.delegate(user1, coin(50_000, "ujuno")).unwrap_err();
+    assert_eq!(
+        ContractError::DepositCapExceeded {
+            cap: Uint128::new(1_000_000),
+            total: Uint128::new(1_050_000)
+        },
+        err.downcast().unwrap()
+    );
+
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    let err = suite.undelegate(user1, coin(499, "ujuno")).unwrap_err();
+    assert_eq!(
+        ContractError::UndelegationTooSmall {
+            min: Uint128::new(500),
+            amount: Uint128::new(499)
+        },
+        err.downcast().unwrap()
+    );
+    suite.undelegate(user1, coin(500, "ujuno")).unwrap();
+
+    // the address cap also applies to received transfers
+    let staked = suite.query_delegated(user1).unwrap().total_staked;
+    let err = suite
+        .transfer(user2, user1, Uint128::new(200_000), None)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::AddressCapExceeded {
+            cap: Uint128::new(600_000),
+            staked: staked + Uint128::new(200_000)
+        },
+        err.downcast().unwrap()
+    );
+
+    // zero lifts the caps
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                deposit_cap: Some(Uint128::zero()),
+                address_cap: Some(Uint128::zero()),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+    suite.delegate(user1, coin(500_000, "ujuno")).unwrap();
+}
+
+#[test]
+fn full_exit_below_min_undelegation() {
+    let (user, fresh_user) = ("user", "fresh_user");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(300, "ujuno"))
+        .with_funds(fresh_user, &coins(300, "ujuno"))
+        .with_minimums(0, 500)
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user, coin(300, "ujuno")).unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    let staked = suite.query_delegated(user).unwrap().total_staked;
+    let err = suite
+        .undelegate(user, coin(staked.u128() - 1, "ujuno"))
+        .unwrap_err();
+    assert_eq!(
+        ContractError::UndelegationTooSmall {
+            min: Uint128::new(500),
+            amount: staked - Uint128::one()
+        },
+        err.downcast().unwrap()
+    );
+
+    // the whole stake can leave even though it's below the minimum
+    suite
+        .undelegate(user, coin(staked.u128(), "ujuno"))
+        .unwrap();
+    assert_eq!(suite.query_pending_claims(user).unwrap(), staked);
+
+    // so can a position that is still only partial stake
+    suite.delegate(fresh_user, coin(300, "ujuno")).unwrap();
+    suite.undelegate(fresh_user, coin(300, "ujuno")).unwrap();
+    assert_eq!(
+        suite.query_pending_claims(fresh_user).unwrap(),
+        Uint128::new(300)
+    );
+    assert_eq!(
+        suite.query_delegated(fresh_user).unwrap().total_staked,
+        Uint128::zero()
+    );
+}
@@ -0,0 +1,173 @@
+use super::suite::SuiteBuilder;
+
+use cosmwasm_std::{coin, coins, Decimal, Uint128, VoteOption};
+
+use crate::error::ContractError;
+
+#[test]
+fn votes_are_weighted_by_stake() {
+    let (user1, user2, user3) = ("user1", "user2", "user3");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(600, "ujuno"))
+        .with_funds(user2, &coins(800, "ujuno"))
+        .with_funds(user3, &coins(300, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user1, coin(600, "ujuno")).unwrap();
+    suite.delegate(user2, coin(400, "ujuno")).unwrap();
+    suite.advance_height(1);
+
+    let err = suite.vote(user1, 1, VoteOption::Yes).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalNotOpen { proposal_id: 1 },
+        err.downcast().unwrap()
+    );
+    let err = suite.open_proposal(user1, 1).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    suite.open_proposal(owner.as_str(), 1).unwrap();
+    let err = suite.open_proposal(owner.as_str(), 1).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalAlreadyOpen { proposal_id: 1 },
+        err.downcast().unwrap()
+    );
+
+    let err = suite.vote(user3, 1, VoteOption::Yes).unwrap_err();
+    assert_eq!(ContractError::NoVotingPower {}, err.downcast().unwrap());
+
+    suite.vote(user1, 1, VoteOption::No).unwrap();
+    // later vote replaces the earlier one
+    suite.vote(user1, 1, VoteOption::Yes).unwrap();
+    suite.vote(user2, 1, VoteOption::NoWithVeto).unwrap();
+
+    let votes = suite.query_proposal_votes(1).unwrap();
+    assert_eq!(
+        votes.tally,
+        vec![
+            (VoteOption::Yes, Uint128::new(600)),
+            (VoteOption::No, Uint128::zero()),
+            (VoteOption::Abstain, Uint128::zero()),
+            (VoteOption::NoWithVeto, Uint128::new(400)),
+        ]
+    );
+    assert_eq!(votes.participation, Decimal::one());
+    assert_eq!(votes.default_vote, None);
+
+    // stake added after the proposal was opened doesn't count
+    suite.advance_height(1);
+    suite.delegate(user2, coin(400, "ujuno")).unwrap();
+    suite.delegate(user3, coin(300, "ujuno")).unwrap();
+    let err = suite.vote(user3, 1, VoteOption::No).unwrap_err();
+    assert_eq!(ContractError::NoVotingPower {}, err.downcast().unwrap());
+    let votes = suite.query_proposal_votes(1).unwrap();
+    assert_eq!(votes.tally[3], (VoteOption::NoWithVeto, Uint128::new(400)));
+    assert_eq!(votes.participation, Decimal::one());
+
+    // votes close with the voting period
+    suite.advance_time(3600 * 24);
+    let err = suite.vote(user2, 1, VoteOption::Yes).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalNotOpen { proposal_id: 1 },
+        err.downcast().unwrap()
+    );
+
+    // votes on other proposals are separate
+    let votes = suite.query_proposal_votes(2).unwrap();
+    assert_eq!(votes.participation, Decimal::zero());
+}
+
+#[test]
+fn default_vote_without_quorum() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+    suite.advance_height(1);
+
+    let err = suite.cast_vote(user, 1).unwrap_err();
+    assert_eq!(
+        ContractError::ProposalNotOpen { proposal_id: 1 },
+        err.downcast().unwrap()
+    );
+    suite.open_proposal(owner.as_str(), 1).unwrap();
+
+    let err = suite.cast_vote(user, 1).unwrap_err();
+    assert_eq!(
+        ContractError::QuorumNotReached {
+            participation: Decimal::zero(),
+            quorum: Decimal::percent(10),
+        },
+        err.downcast().unwrap()
+    );
+
+    let err = suite
+        .set_default_vote(user, 1, VoteOption::Abstain)
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    suite
+        .set_default_vote(owner.as_str(), 1, VoteOption::Abstain)
+        .unwrap();
+    assert_eq!(
+        suite.query_proposal_votes(1).unwrap().default_vote,
+        Some(VoteOption::Abstain)
+    );
+}
+
+#[test]
+fn dao_voting_module_queries() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(600, "ujuno"))
+        .with_funds(user2, &coins(400, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    assert_eq!(suite.query_dao().unwrap(), owner);
+    let info = suite.query_info().unwrap();
+    assert_eq!(info.contract, "crates.io:interstake-yield-generator");
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+
+    suite.delegate(user1, coin(600, "ujuno")).unwrap();
+    suite.advance_height(5);
+    let height = suite.block_height();
+    // partial stakes count as voting power
+    suite.delegate(user2, coin(400, "ujuno")).unwrap();
+    suite.advance_height(5);
+
+    let power = suite.query_voting_power_at_height(user2, height).unwrap();
+    assert_eq!(power.power, Uint128::zero());
+    assert_eq!(power.height, height);
+    let power = suite.query_voting_power_at_height(user2, None).unwrap();
+    assert_eq!(power.power, Uint128::new(400));
+    assert_eq!(power.height, suite.block_height());
+
+    let total = suite.query_total_power_at_height(height).unwrap();
+    assert_eq!(total.power, Uint128::new(600));
+    let total = suite.query_total_power_at_height(None).unwrap();
+    assert_eq!(total.power, Uint128::new(1000));
+
+    // queued undelegations leave the voting power right away, but stay in the total stake
+    // until they're unbonded
+    suite.advance_time(3600 * 24);
+    suite.restake(owner.as_str()).unwrap();
+    suite.undelegate(user1, coin(100, "ujuno")).unwrap();
+    suite.advance_height(1);
+    let power1 = suite
+        .query_voting_power_at_height(user1, None)
+        .unwrap()
+        .power;
+    let power2 = suite
+        .query_voting_power_at_height(user2, None)
+        .unwrap()
+        .power;
+    let total = suite.query_total_power_at_height(None).unwrap().power;
+    assert_eq!(total, power1 + power2);
+    assert_eq!(
+        suite.query_total_at_height(None).unwrap(),
+        total + Uint128::new(100)
+    );
+}
@@ -0,0 +1,192 @@
+use super::suite::SuiteBuilder;
+
+use cosmwasm_std::{assert_approx_eq, coin, coins, Decimal, Uint128};
+
+use crate::error::ContractError;
+
+const ONE_DAY: u64 = 3600 * 24;
+const THIRTY_DAYS: u64 = ONE_DAY * 30;
+
+#[test]
+fn locked_stake_earns_boosted_rewards() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(1_000_000, "ujuno"))
+        .with_funds(user2, &coins(1_000_000, "ujuno"))
+        .with_lock_tiers(&[(THIRTY_DAYS, Decimal::percent(200))])
+        .build();
+    let owner = suite.owner();
+
+    let err = suite
+        .delegate_locked(user1, coin(1_000_000, "ujuno"), ONE_DAY)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::InvalidLockDuration { duration: ONE_DAY },
+        err.downcast().unwrap()
+    );
+
+    suite
+        .delegate_locked(user1, coin(1_000_000, "ujuno"), THIRTY_DAYS)
+        .unwrap();
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+
+    let locks = suite.query_locks(user1).unwrap();
+    assert_eq!(locks.len(), 1);
+    assert_eq!(locks[0].0, 1);
+    assert_eq!(locks[0].1.amount, Uint128::new(1_000_000));
+    assert_eq!(locks[0].1.multiplier, Decimal::percent(200));
+
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    // the locked stake weighs twice as much
+    let locked_earnings = suite.query_delegated(user1).unwrap().total_earnings;
+    let unlocked_earnings = suite.query_delegated(user2).unwrap().total_earnings;
+    assert_approx_eq!(
+        locked_earnings.u128(),
+        unlocked_earnings.u128() * 2,
+        "0.0001"
+    );
+}
+
+#[test]
+fn locked_stake_cant_leave() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(2_000_000, "ujuno"))
+        .with_lock_tiers(&[(THIRTY_DAYS, Decimal::percent(150))])
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user, coin(1_000_000, "ujuno")).unwrap();
+    suite
+        .delegate_locked(user, coin(1_000_000, "ujuno"), THIRTY_DAYS)
+        .unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    let err = suite
+        .undelegate(user, coin(1_500_000, "ujuno"))
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::StakeLocked { locked, .. } if locked == Uint128::new(1_000_000)
+    ));
+    let err = suite
+        .transfer(user, "recipient", Uint128::new(1_500_000), None)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::StakeLocked { .. }
+    ));
+
+    // unlocked stake can still leave
+    suite.undelegate(user, coin(1_000_000, "ujuno")).unwrap();
+
+    // early unlock forfeits 10% of the lock to the treasury
+    let staked = suite.query_delegated(user).unwrap().total_staked;
+    let treasury = suite.treasury();
+    suite.unlock(user, 1).unwrap();
+    assert_eq!(
+        suite.query_delegated(user).unwrap().total_staked,
+        staked - Uint128::new(100_000)
+    );
+    assert_eq!(
+        suite
+            .query_delegated(treasury.as_str())
+            .unwrap()
+            .total_staked,
+        Uint128::new(100_000)
+    );
+    assert_eq!(suite.query_locks(user).unwrap(), vec![]);
+
+    let err = suite.unlock(user, 1).unwrap_err();
+    assert_eq!(
+        ContractError::LockNotFound { id: 1 },
+        err.downcast().unwrap()
+    );
+    suite.undelegate(user, coin(900_000, "ujuno")).unwrap();
+}
+
+#[test]
+fn expired_lock_unlocks_without_penalty() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1_000_000, "ujuno"))
+        .with_lock_tiers(&[(THIRTY_DAYS, Decimal::percent(150))])
+        .build();
+
+    suite
+        .delegate_locked(user, coin(1_000_000, "ujuno"), THIRTY_DAYS)
+        .unwrap();
+    suite.advance_time(THIRTY_DAYS);
+
+    // an expired lock doesn't hold the stake back anymore
+    suite.undelegate(user, coin(500_000, "ujuno")).unwrap();
+    suite.unlock(user, 1).unwrap();
+    assert_eq!(
+        suite.query_delegated(user).unwrap().total_staked,
+        Uint128::new(500_000)
+    );
+}
+
+#[test]
+fn unlocking_partial_lock() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1_000_000, "ujuno"))
+        .with_lock_tiers(&[(THIRTY_DAYS, Decimal::percent(150))])
+        .build();
+    let treasury = suite.treasury();
+
+    // the lock is still a partial stake, the penalty comes out of it
+    suite
+        .delegate_locked(user, coin(1_000_000, "ujuno"), THIRTY_DAYS)
+        .unwrap();
+    suite.unlock(user, 1).unwrap();
+    assert_eq!(
+        suite.query_delegated(user).unwrap().total_staked,
+        Uint128::new(900_000)
+    );
+    assert_eq!(
+        suite
+            .query_delegated(treasury.as_str())
+            .unwrap()
+            .total_staked,
+        Uint128::new(100_000)
+    );
+}
+
+#[test]
+fn undelegate_all_clears_locks() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(2_000_000, "ujuno"))
+        .with_funds(user2, &coins(2_000_000, "ujuno"))
+        .with_lock_tiers(&[(THIRTY_DAYS, Decimal::percent(200))])
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .delegate_locked(user1, coin(1_000_000, "ujuno"), THIRTY_DAYS)
+        .unwrap();
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+    suite.undelegate_all_resumable(owner.as_str()).unwrap();
+    assert_eq!(suite.query_locks(user1).unwrap(), vec![]);
+
+    // a stale lock would keep boosting the new stake
+    suite.resume(owner.as_str()).unwrap();
+    suite.delegate(user1, coin(1_000_000, "ujuno")).unwrap();
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+    let before1 = suite.query_delegated(user1).unwrap().total_earnings;
+    let before2 = suite.query_delegated(user2).unwrap().total_earnings;
+    suite.advance_time(ONE_DAY);
+    suite.restake(owner.as_str()).unwrap();
+
+    let earnings1 = suite.query_delegated(user1).unwrap().total_earnings - before1;
+    let earnings2 = suite.query_delegated(user2).unwrap().total_earnings - before2;
+    assert!(!earnings1.is_zero());
+    assert_approx_eq!(earnings1.u128(), earnings2.u128(), "0.0001");
+}
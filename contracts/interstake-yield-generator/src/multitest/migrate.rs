@@ -44,13 +44,29 @@ fn recently_failed_migration() {
             suite.contract_code_id,
             &MigrateMsg {
                 owner: owner.to_string(),
-                treasury: treasury.to_string(),
+                treasury: vec![(treasury.to_string(), Decimal::one())],
                 staking_addr: VALIDATOR_1.to_string(),
                 restake_commission,
                 transfer_commission,
                 denom: "ujuno".to_string(),
                 unbonding_period: None,
                 max_entries: Some(7),
+                drift_threshold: None,
+                referral_commission: None,
+                referral_period: None,
+                restake_commission_payout: None,
+                transfer_commission_payout: None,
+                timelock_delay: None,
+                vote_quorum: None,
+                lock_tiers: None,
+                early_unlock_penalty: None,
+                early_exit_fee: None,
+                early_exit_blocks: None,
+                early_exit_treasury_share: None,
+                deposit_cap: None,
+                address_cap: None,
+                min_delegation: None,
+                min_undelegation: None,
             },
         )
         .unwrap();
@@ -68,13 +84,29 @@ fn recently_failed_migration() {
             suite.contract_code_id,
             &MigrateMsg {
                 owner: owner.to_string(),
-                treasury: treasury.to_string(),
+                treasury: vec![(treasury.to_string(), Decimal::one())],
                 staking_addr: VALIDATOR_1.to_string(),
                 restake_commission,
                 transfer_commission,
                 denom: "ujuno".to_string(),
                 unbonding_period: None,
                 max_entries: Some(7),
+                drift_threshold: None,
+                referral_commission: None,
+                referral_period: None,
+                restake_commission_payout: None,
+                transfer_commission_payout: None,
+                timelock_delay: None,
+                vote_quorum: None,
+                lock_tiers: None,
+                early_unlock_penalty: None,
+                early_exit_fee: None,
+                early_exit_blocks: None,
+                early_exit_treasury_share: None,
+                deposit_cap: None,
+                address_cap: None,
+                min_delegation: None,
+                min_undelegation: None,
             },
         )
         .unwrap();
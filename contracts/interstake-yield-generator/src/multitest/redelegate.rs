@@ -0,0 +1,446 @@
+use super::suite::{
+    validator_list, Suite, SuiteBuilder, TWENTY_EIGHT_DAYS, VALIDATOR_1, VALIDATOR_2,
+};
+
+use cosmwasm_std::{coin, Decimal, StakingMsg, Uint128};
+use cw_utils::Expiration;
+
+use crate::contract::utils::{
+    compute_rebalance_msgs, remaining_validator_weights, underweight_delegate_msgs,
+};
+use crate::error::ContractError;
+use crate::msg::{ConfigUpdate, RemovalMode};
+use crate::state::RebondDetails;
+
+fn delegated_to(suite: &Suite, validator: &str) -> Uint128 {
+    suite
+        .query_all_delegations()
+        .unwrap()
+        .into_iter()
+        .filter(|delegation| delegation.validator == validator)
+        .map(|delegation| delegation.amount.amount)
+        .sum()
+}
+
+#[test]
+fn redelegation_blocks_destination_as_source() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &[coin(1000, "ujuno")])
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+    assert_eq!(suite.query_blocked_validators().unwrap(), vec![]);
+
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![(VALIDATOR_2.to_owned(), Decimal::one())],
+        )
+        .unwrap();
+    let completion =
+        Expiration::AtTime(suite.app.block_info().time.plus_seconds(TWENTY_EIGHT_DAYS));
+    assert_eq!(
+        suite.query_blocked_validators().unwrap(),
+        vec![(VALIDATOR_2.to_owned(), completion)]
+    );
+
+    // moving the stake back right away would be a transitive redelegation,
+    // so it gets unbonded from validator2 instead
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![(VALIDATOR_1.to_owned(), Decimal::one())],
+        )
+        .unwrap();
+    assert_eq!(
+        suite.query_pending_rebonds().unwrap(),
+        vec![RebondDetails {
+            validator: Some(VALIDATOR_1.to_owned()),
+            release_timestamp: completion,
+            amount: coin(1000, "ujuno"),
+        }]
+    );
+    // nothing got redelegated into validator1
+    assert_eq!(
+        suite.query_blocked_validators().unwrap(),
+        vec![(VALIDATOR_2.to_owned(), completion)]
+    );
+
+    let err = suite.rebond(user).unwrap_err();
+    assert_eq!(ContractError::NoMaturedRebonds {}, err.downcast().unwrap());
+
+    suite.advance_time(TWENTY_EIGHT_DAYS);
+    suite.process_staking_queue().unwrap();
+    assert_eq!(suite.query_blocked_validators().unwrap(), vec![]);
+
+    // anyone can delegate released tokens back
+    suite.rebond(user).unwrap();
+    assert_eq!(suite.query_pending_rebonds().unwrap(), vec![]);
+
+    assert_eq!(delegated_to(&suite, VALIDATOR_1), Uint128::new(1000));
+}
+
+#[test]
+fn blocked_source_redelegates_from_another_validator() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_multiple_validators(3)
+        .with_funds(user, &[coin(1000, "ujuno")])
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![
+                (VALIDATOR_1.to_owned(), Decimal::percent(50)),
+                ("validator3".to_owned(), Decimal::percent(50)),
+            ],
+        )
+        .unwrap();
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![
+                (VALIDATOR_2.to_owned(), Decimal::percent(50)),
+                ("validator3".to_owned(), Decimal::percent(50)),
+            ],
+        )
+        .unwrap();
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::new(500));
+
+    // validator2 stays listed but can't give its stake away yet, so validator3 covers it
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![
+                (VALIDATOR_1.to_owned(), Decimal::percent(25)),
+                (VALIDATOR_2.to_owned(), Decimal::percent(25)),
+                ("validator3".to_owned(), Decimal::percent(50)),
+            ],
+        )
+        .unwrap();
+    assert_eq!(suite.query_pending_rebonds().unwrap(), vec![]);
+    assert_eq!(delegated_to(&suite, VALIDATOR_1), Uint128::new(250));
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::new(500));
+    assert_eq!(delegated_to(&suite, "validator3"), Uint128::new(250));
+
+    let completion =
+        Expiration::AtTime(suite.app.block_info().time.plus_seconds(TWENTY_EIGHT_DAYS));
+    assert_eq!(
+        suite.query_blocked_validators().unwrap(),
+        vec![
+            (VALIDATOR_1.to_owned(), completion),
+            (VALIDATOR_2.to_owned(), completion)
+        ]
+    );
+}
+
+#[test]
+fn rebalance_after_slashing() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &[coin(100_000_000, "ujuno")])
+        .build();
+    suite
+        .update_validator_list(suite.owner().as_str(), validator_list(2))
+        .unwrap();
+    suite.delegate(user, coin(100_000_000, "ujuno")).unwrap();
+
+    let drift = suite.query_allocation_drift().unwrap();
+    assert_eq!(drift.max_drift, Decimal::zero());
+    assert_eq!(drift.threshold, Decimal::percent(5));
+    let err = suite.rebalance(user).unwrap_err();
+    assert_eq!(
+        ContractError::DriftBelowThreshold {
+            drift: Decimal::zero(),
+            threshold: Decimal::percent(5)
+        },
+        err.downcast().unwrap()
+    );
+
+    // validator1 gets slashed, so it holds 1/3 of the stake instead of 1/2
+    suite.slash(VALIDATOR_1, Decimal::percent(50)).unwrap();
+    let drift = suite.query_allocation_drift().unwrap();
+    assert_eq!(drift.validators[0].delegated, Uint128::new(25_000_000));
+    assert_eq!(
+        drift.validators[0].actual_share,
+        Decimal::from_ratio(1u128, 3u128)
+    );
+    assert_eq!(
+        drift.max_drift,
+        Decimal::percent(50) - Decimal::from_ratio(1u128, 3u128)
+    );
+
+    // anyone can trigger the rebalance now
+    suite.rebalance(user).unwrap();
+    assert_eq!(delegated_to(&suite, VALIDATOR_1), Uint128::new(37_500_000));
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::new(37_500_000));
+    assert_eq!(
+        suite.query_blocked_validators().unwrap(),
+        vec![(
+            VALIDATOR_1.to_owned(),
+            Expiration::AtTime(suite.app.block_info().time.plus_seconds(TWENTY_EIGHT_DAYS))
+        )]
+    );
+    assert_eq!(
+        suite.query_allocation_drift().unwrap().max_drift,
+        Decimal::zero()
+    );
+}
+
+#[test]
+fn restake_fills_underweight_validators() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &[coin(100_000_000, "ujuno")])
+        .build();
+    let owner = suite.owner();
+    suite
+        .update_validator_list(owner.as_str(), validator_list(2))
+        .unwrap();
+    suite.delegate(user, coin(100_000_000, "ujuno")).unwrap();
+    suite.slash(VALIDATOR_1, Decimal::percent(50)).unwrap();
+
+    // reward is much smaller than what validator1 misses, so it all goes there
+    suite.advance_time(3600 * 24);
+    let reward = suite.query_reward().unwrap().amount;
+    suite.restake(owner.as_str()).unwrap();
+    assert_eq!(
+        delegated_to(&suite, VALIDATOR_1),
+        Uint128::new(25_000_000) + reward
+    );
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::new(50_000_000));
+
+    // raising the threshold above current drift restores splitting by weight
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                drift_threshold: Some(Decimal::percent(50)),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+    suite.advance_time(3600 * 24);
+    let delegated_before = delegated_to(&suite, VALIDATOR_2);
+    let reward = suite.query_reward().unwrap().amount;
+    suite.restake(owner.as_str()).unwrap();
+    assert_eq!(
+        delegated_to(&suite, VALIDATOR_2),
+        delegated_before + reward * Decimal::percent(50)
+    );
+}
+
+fn allocations_of(delegated: &[(&str, u64, u128)]) -> Vec<(String, Decimal, Uint128)> {
+    delegated
+        .iter()
+        .map(|(validator, weight, amount)| {
+            (
+                validator.to_string(),
+                Decimal::percent(*weight),
+                Uint128::new(*amount),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn compute_rebalance_moves_surplus_to_deficit() {
+    let allocations = allocations_of(&[
+        ("validator1", 50, 700),
+        ("validator2", 30, 100),
+        ("validator3", 20, 200),
+    ]);
+
+    assert_eq!(
+        compute_rebalance_msgs(&allocations, "ujuno"),
+        vec![StakingMsg::Redelegate {
+            src_validator: "validator1".to_owned(),
+            dst_validator: "validator2".to_owned(),
+            amount: coin(200, "ujuno")
+        }]
+    );
+}
+
+#[test]
+fn underweight_delegations_split_reward() {
+    let allocations = allocations_of(&[
+        ("validator1", 50, 300),
+        ("validator2", 25, 300),
+        ("validator3", 25, 100),
+    ]);
+
+    // reward 100 -> total 800, validator1 misses 100, validator3 misses 100
+    assert_eq!(
+        underweight_delegate_msgs(&allocations, coin(100, "ujuno")),
+        vec![
+            StakingMsg::Delegate {
+                validator: "validator1".to_owned(),
+                amount: coin(50, "ujuno")
+            },
+            StakingMsg::Delegate {
+                validator: "validator3".to_owned(),
+                amount: coin(50, "ujuno")
+            },
+        ]
+    );
+
+    // validator2 has a surplus, so deficits are larger than the reward
+    let allocations = allocations_of(&[
+        ("validator1", 50, 100),
+        ("validator2", 25, 500),
+        ("validator3", 25, 100),
+    ]);
+    assert_eq!(
+        underweight_delegate_msgs(&allocations, coin(100, "ujuno")),
+        vec![
+            StakingMsg::Delegate {
+                validator: "validator1".to_owned(),
+                amount: coin(75, "ujuno")
+            },
+            StakingMsg::Delegate {
+                validator: "validator3".to_owned(),
+                amount: coin(25, "ujuno")
+            },
+        ]
+    );
+}
+
+#[test]
+fn remove_validator_by_redelegation() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_multiple_validators(3)
+        .with_funds(user, &[coin(1000, "ujuno")])
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_validator_list(
+            owner.as_str(),
+            vec![
+                (VALIDATOR_1.to_owned(), Decimal::percent(50)),
+                (VALIDATOR_2.to_owned(), Decimal::percent(25)),
+                ("validator3".to_owned(), Decimal::percent(25)),
+            ],
+        )
+        .unwrap();
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+
+    let err = suite
+        .remove_validator(user, VALIDATOR_1, RemovalMode::Redelegate)
+        .unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+    suite
+        .remove_validator(owner.as_str(), VALIDATOR_1, RemovalMode::Redelegate)
+        .unwrap();
+    assert_eq!(
+        suite.query_validator_list().unwrap(),
+        vec![
+            (VALIDATOR_2.to_owned(), Decimal::percent(50)),
+            ("validator3".to_owned(), Decimal::percent(50)),
+        ]
+    );
+    assert_eq!(delegated_to(&suite, VALIDATOR_1), Uint128::zero());
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::new(500));
+    assert_eq!(delegated_to(&suite, "validator3"), Uint128::new(500));
+
+    let err = suite
+        .remove_validator(owner.as_str(), VALIDATOR_1, RemovalMode::Redelegate)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::ValidatorNotFound {
+            validator: VALIDATOR_1.to_owned()
+        },
+        err.downcast().unwrap()
+    );
+}
+
+#[test]
+fn remove_validator_by_undelegation() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &[coin(1000, "ujuno")])
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_validator_list(owner.as_str(), validator_list(2))
+        .unwrap();
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+
+    suite
+        .remove_validator(owner.as_str(), VALIDATOR_2, RemovalMode::Undelegate)
+        .unwrap();
+    assert_eq!(
+        suite.query_validator_list().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), Decimal::one())]
+    );
+    assert_eq!(delegated_to(&suite, VALIDATOR_2), Uint128::zero());
+
+    let release_timestamp =
+        Expiration::AtTime(suite.app.block_info().time.plus_seconds(TWENTY_EIGHT_DAYS));
+    assert_eq!(
+        suite.query_pending_rebonds().unwrap(),
+        vec![RebondDetails {
+            validator: None,
+            release_timestamp,
+            amount: coin(500, "ujuno"),
+        }]
+    );
+
+    let err = suite
+        .remove_validator(owner.as_str(), VALIDATOR_1, RemovalMode::Undelegate)
+        .unwrap_err();
+    assert_eq!(
+        ContractError::CannotRemoveLastValidator {},
+        err.downcast().unwrap()
+    );
+
+    suite.advance_time(TWENTY_EIGHT_DAYS);
+    suite.process_staking_queue().unwrap();
+
+    // released tokens go to the remaining validators
+    suite.rebond(user).unwrap();
+    assert_eq!(suite.query_pending_rebonds().unwrap(), vec![]);
+    assert_eq!(delegated_to(&suite, VALIDATOR_1), Uint128::new(1000));
+}
+
+#[test]
+fn remaining_weights_sum_to_one() {
+    let validators = validator_list(3);
+
+    assert_eq!(
+        remaining_validator_weights(&validators, "validator2").unwrap(),
+        vec![
+            ("validator1".to_owned(), Decimal::percent(50)),
+            ("validator3".to_owned(), Decimal::percent(50)),
+        ]
+    );
+
+    let validators = vec![
+        ("validator1".to_owned(), Decimal::percent(70)),
+        ("validator2".to_owned(), Decimal::percent(20)),
+        ("validator3".to_owned(), Decimal::percent(10)),
+    ];
+    let weights = remaining_validator_weights(&validators, "validator1").unwrap();
+    assert_eq!(
+        weights,
+        vec![
+            ("validator2".to_owned(), Decimal::from_ratio(2u128, 3u128)),
+            (
+                "validator3".to_owned(),
+                Decimal::one() - Decimal::from_ratio(2u128, 3u128)
+            ),
+        ]
+    );
+
+    let err = remaining_validator_weights(&validators[..1], "validator1").unwrap_err();
+    assert_eq!(ContractError::CannotRemoveLastValidator {}, err);
+}
@@ -1,4 +1,5 @@
 use anyhow::Result as AnyResult;
+use cw2::ContractVersion;
 use cw_utils::Expiration;
 use interstake_yield_generator_v02::contract as yield_generator_v02;
 use interstake_yield_generator_v02::msg as msg_v02;
@@ -10,8 +11,8 @@ use serde::Serialize;
 use std::fmt;
 
 use cosmwasm_std::{
-    Addr, AllDelegationsResponse, BlockInfo, Coin, Decimal, Delegation, StakingQuery, Uint128,
-    Validator,
+    Addr, AllDelegationsResponse, BlockInfo, Coin, Decimal, Delegation, Empty, StakingQuery,
+    Uint128, Validator, VoteOption,
 };
 use cw_multi_test::{
     App, AppResponse, Contract, ContractWrapper, Executor, StakingInfo, StakingSudo, SudoMsg,
@@ -19,14 +20,24 @@ use cw_multi_test::{
 
 use crate::msg::PendingClaimResponse;
 use crate::msg::{
-    AllowedAddrResponse, ClaimsResponse, ConfigResponse, DelegateResponse, DelegatedResponse,
-    ExecuteMsg, InstantiateMsg, LastPaymentBlockResponse, QueryMsg, RewardResponse,
-    TotalDelegatedResponse, ValidatorsResponse,
+    AdminHistoryResponse, AllocationDriftResponse, AllowedAddrResponse, BlockedValidatorsResponse,
+    ClaimsResponse, CommissionStatsResponse, ConfigResponse, ConfigUpdate, CurrentAprResponse,
+    DelegateResponse, DelegatedResponse, ExecuteMsg, HooksResponse, InfoResponse, InstantiateMsg,
+    LastPaymentBlockResponse, LiquidRewardsResponse, LocksResponse, PendingProposalsResponse,
+    PendingRebondsResponse, ProposalVotesResponse, QueryMsg, ReferrerStatsResponse, RemovalMode,
+    RewardResponse, RewardsByValidatorResponse, SimulateUndelegateResponse, StakeAtHeightResponse,
+    StatusResponse, TotalAtHeightResponse, TotalDelegatedResponse, TotalPowerAtHeightResponse,
+    UnbondingEntriesResponse, UserEarningsResponse, ValidatorRewards, ValidatorsResponse,
+    VotingPowerAtHeightResponse, YieldHistoryResponse,
+};
+use crate::state::{
+    AdminAction, ClaimDetails, Config, ContractStatus, Lock, LockTier, PayoutMode, PendingProposal,
+    RebondDetails, RewardMode, YieldEntry, YieldRecipient,
 };
-use crate::state::{ClaimDetails, Config};
 
 pub const TWENTY_EIGHT_DAYS: u64 = 3600 * 24 * 28;
 pub const FOUR_DAYS: u64 = 3600 * 24 * 4;
+pub const ONE_YEAR: u64 = 3600 * 24 * 365;
 
 pub fn contract_yield_generator<C>() -> Box<dyn Contract<C>>
 where
@@ -41,6 +52,48 @@ where
     Box::new(contract)
 }
 
+mod hook_receiver {
+    use cosmwasm_std::{Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdResult};
+
+    use crate::msg::StakeChangedHookMsg;
+
+    pub fn execute(
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        msg: StakeChangedHookMsg,
+    ) -> StdResult<Response> {
+        let StakeChangedHookMsg::StakeChangedHook(change) = msg;
+        Ok(Response::new()
+            .add_attribute("addr", change.addr)
+            .add_attribute("old", change.old)
+            .add_attribute("new", change.new))
+    }
+
+    pub fn instantiate(
+        _deps: DepsMut,
+        _env: Env,
+        _info: MessageInfo,
+        _msg: Empty,
+    ) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+
+    pub fn query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+        Ok(Binary::default())
+    }
+}
+
+/// Accepts stake change hooks and reports them as attributes
+pub fn contract_hook_receiver() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new_with_empty(
+        hook_receiver::execute,
+        hook_receiver::instantiate,
+        hook_receiver::query,
+    );
+    Box::new(contract)
+}
+
 pub fn contract_yield_generator_v03<C>() -> Box<dyn Contract<C>>
 where
     C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
@@ -76,6 +129,15 @@ pub struct SuiteBuilder {
     pub number_of_validators: u32,
     pub funds: Vec<(Addr, Vec<Coin>)>,
     pub denom: String,
+    pub max_entries: u64,
+    pub timelock_delay: Option<u64>,
+    pub lock_tiers: Option<Vec<LockTier>>,
+    pub early_exit_fee: Option<Decimal>,
+    pub early_exit_blocks: Option<u64>,
+    pub deposit_cap: Option<Uint128>,
+    pub address_cap: Option<Uint128>,
+    pub min_delegation: Option<Uint128>,
+    pub min_undelegation: Option<Uint128>,
 }
 
 pub const VALIDATOR_1: &str = "validator1";
@@ -92,6 +154,15 @@ impl SuiteBuilder {
             treasury: "treasury".to_owned(),
             funds: vec![],
             denom: "ujuno".to_owned(),
+            max_entries: 7,
+            timelock_delay: None,
+            lock_tiers: None,
+            early_exit_fee: None,
+            early_exit_blocks: None,
+            deposit_cap: None,
+            address_cap: None,
+            min_delegation: None,
+            min_undelegation: None,
         }
     }
 
@@ -118,6 +189,47 @@ impl SuiteBuilder {
         self
     }
 
+    pub fn with_max_entries(mut self, max_entries: u64) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn with_timelock_delay(mut self, delay: u64) -> Self {
+        self.timelock_delay = Some(delay);
+        self
+    }
+
+    pub fn with_lock_tiers(mut self, tiers: &[(u64, Decimal)]) -> Self {
+        self.lock_tiers = Some(
+            tiers
+                .iter()
+                .map(|&(duration, multiplier)| LockTier {
+                    duration,
+                    multiplier,
+                })
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_early_exit_fee(mut self, fee: Decimal, blocks: u64) -> Self {
+        self.early_exit_fee = Some(fee);
+        self.early_exit_blocks = Some(blocks);
+        self
+    }
+
+    pub fn with_deposit_caps(mut self, deposit_cap: u128, address_cap: u128) -> Self {
+        self.deposit_cap = Some(deposit_cap.into());
+        self.address_cap = Some(address_cap.into());
+        self
+    }
+
+    pub fn with_minimums(mut self, min_delegation: u128, min_undelegation: u128) -> Self {
+        self.min_delegation = Some(min_delegation.into());
+        self.min_undelegation = Some(min_undelegation.into());
+        self
+    }
+
     #[track_caller]
     pub fn build(self) -> Suite {
         let owner = Addr::unchecked(self.owner.clone());
@@ -170,13 +282,29 @@ impl SuiteBuilder {
                 owner.clone(),
                 &InstantiateMsg {
                     owner: self.owner.clone(),
-                    treasury: self.treasury.clone(),
+                    treasury: vec![(self.treasury.clone(), Decimal::one())],
                     staking_addr: VALIDATOR_1.to_owned(),
                     restake_commission: self.restake_commission,
                     transfer_commission: self.restake_commission,
                     denom: self.denom.clone(),
                     unbonding_period: Some(TWENTY_EIGHT_DAYS),
-                    max_entries: Some(7),
+                    max_entries: Some(self.max_entries),
+                    drift_threshold: None,
+                    referral_commission: None,
+                    referral_period: None,
+                    restake_commission_payout: None,
+                    transfer_commission_payout: None,
+                    timelock_delay: self.timelock_delay,
+                    vote_quorum: None,
+                    lock_tiers: self.lock_tiers.clone(),
+                    early_unlock_penalty: None,
+                    early_exit_fee: self.early_exit_fee,
+                    early_exit_blocks: self.early_exit_blocks,
+                    early_exit_treasury_share: None,
+                    deposit_cap: self.deposit_cap,
+                    address_cap: self.address_cap,
+                    min_delegation: self.min_delegation,
+                    min_undelegation: self.min_undelegation,
                 },
                 &[],
                 "yield_generator",
@@ -280,6 +408,10 @@ impl Suite {
         })
     }
 
+    pub fn block_height(&self) -> u64 {
+        self.app.block_info().height
+    }
+
     pub fn advance_time(&mut self, time: u64) {
         self.app.update_block(|block: &mut BlockInfo| {
             block.time = block.time.plus_seconds(time);
@@ -296,7 +428,7 @@ impl Suite {
         &mut self,
         sender: &str,
         owner: impl Into<Option<String>>,
-        treasury: impl Into<Option<String>>,
+        treasury: impl Into<Option<Vec<(String, Decimal)>>>,
         restake_commission: impl Into<Option<Decimal>>,
         transfer_commission: impl Into<Option<Decimal>>,
         unbonding_period: impl Into<Option<u64>>,
@@ -304,13 +436,315 @@ impl Suite {
         self.app.execute_contract(
             Addr::unchecked(sender),
             self.contract.clone(),
-            &ExecuteMsg::UpdateConfig {
+            &ExecuteMsg::UpdateConfig(ConfigUpdate {
                 owner: owner.into(),
                 treasury: treasury.into(),
                 restake_commission: restake_commission.into(),
                 transfer_commission: transfer_commission.into(),
                 unbonding_period: unbonding_period.into(),
+                ..ConfigUpdate::default()
+            }),
+            &[],
+        )
+    }
+
+    pub fn update_config_with(
+        &mut self,
+        sender: &str,
+        update: ConfigUpdate,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::UpdateConfig(update),
+            &[],
+        )
+    }
+
+    pub fn propose_config_change(
+        &mut self,
+        sender: &str,
+        update: ConfigUpdate,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::ProposeConfigChange(update),
+            &[],
+        )
+    }
+
+    pub fn propose_validator_list(
+        &mut self,
+        sender: &str,
+        new_validator_list: Vec<(String, Decimal)>,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::ProposeValidatorList { new_validator_list },
+            &[],
+        )
+    }
+
+    pub fn execute_pending(&mut self, sender: &str, id: u64) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::ExecutePending { id },
+            &[],
+        )
+    }
+
+    pub fn cancel_pending(&mut self, sender: &str, id: u64) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::CancelPending { id },
+            &[],
+        )
+    }
+
+    /// Opens `proposal_id` for votes until a day from now
+    pub fn open_proposal(&mut self, sender: &str, proposal_id: u64) -> AnyResult<AppResponse> {
+        let voting_end = self.app.block_info().time.plus_seconds(3600 * 24);
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::OpenProposal {
+                proposal_id,
+                voting_end,
+            },
+            &[],
+        )
+    }
+
+    pub fn vote(
+        &mut self,
+        sender: &str,
+        proposal_id: u64,
+        vote: VoteOption,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Vote { proposal_id, vote },
+            &[],
+        )
+    }
+
+    pub fn instantiate_hook_receiver(&mut self) -> Addr {
+        let code_id = self.app.store_code(contract_hook_receiver());
+        self.app
+            .instantiate_contract(
+                code_id,
+                self.owner.clone(),
+                &Empty {},
+                &[],
+                "hook_receiver",
+                None,
+            )
+            .unwrap()
+    }
+
+    pub fn add_hook(&mut self, sender: &str, addr: &Addr) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::AddHook {
+                addr: addr.to_string(),
+            },
+            &[],
+        )
+    }
+
+    pub fn remove_hook(&mut self, sender: &str, addr: &Addr) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::RemoveHook {
+                addr: addr.to_string(),
+            },
+            &[],
+        )
+    }
+
+    pub fn query_yield_history(
+        &self,
+        start_after: impl Into<Option<u64>>,
+        limit: impl Into<Option<u32>>,
+    ) -> AnyResult<Vec<(u64, YieldEntry)>> {
+        let response: YieldHistoryResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::YieldHistory {
+                start_after: start_after.into(),
+                limit: limit.into(),
+            },
+        )?;
+        Ok(response.entries)
+    }
+
+    pub fn query_user_earnings(
+        &self,
+        address: &str,
+        start_after: impl Into<Option<u64>>,
+        limit: impl Into<Option<u32>>,
+    ) -> AnyResult<Vec<(u64, Uint128)>> {
+        let response: UserEarningsResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::UserEarnings {
+                address: address.to_owned(),
+                start_after: start_after.into(),
+                limit: limit.into(),
+            },
+        )?;
+        Ok(response.earnings)
+    }
+
+    pub fn query_current_apr(
+        &self,
+        window: impl Into<Option<u64>>,
+    ) -> AnyResult<CurrentAprResponse> {
+        let response = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::CurrentApr {
+                window: window.into(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_liquid_rewards(&self, address: &str) -> AnyResult<LiquidRewardsResponse> {
+        let response = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::LiquidRewards {
+                address: address.to_owned(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_hooks(&self) -> AnyResult<Vec<String>> {
+        let response: HooksResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::Hooks {})?;
+        Ok(response.hooks)
+    }
+
+    pub fn set_reward_mode(&mut self, sender: &str, mode: RewardMode) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::SetRewardMode { mode },
+            &[],
+        )
+    }
+
+    pub fn claim_rewards(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::ClaimRewards {},
+            &[],
+        )
+    }
+
+    pub fn set_yield_recipient(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        ratio: Decimal,
+        payout: impl Into<Option<PayoutMode>>,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::SetYieldRecipient {
+                recipient: recipient.to_owned(),
+                ratio,
+                payout: payout.into(),
+            },
+            &[],
+        )
+    }
+
+    pub fn clear_yield_recipient(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::ClearYieldRecipient {},
+            &[],
+        )
+    }
+
+    pub fn delegate_locked(
+        &mut self,
+        sender: &str,
+        amount: Coin,
+        duration: u64,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::DelegateLocked { duration },
+            &[amount],
+        )
+    }
+
+    pub fn unlock(&mut self, sender: &str, id: u64) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Unlock { id },
+            &[],
+        )
+    }
+
+    pub fn query_locks(&self, address: &str) -> AnyResult<Vec<(u64, Lock)>> {
+        let response: LocksResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::Locks {
+                address: address.to_owned(),
             },
+        )?;
+        Ok(response.locks)
+    }
+
+    pub fn query_simulate_undelegate(
+        &self,
+        address: &str,
+        amount: u128,
+    ) -> AnyResult<SimulateUndelegateResponse> {
+        let response: SimulateUndelegateResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::SimulateUndelegate {
+                address: address.to_owned(),
+                amount: amount.into(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn set_default_vote(
+        &mut self,
+        sender: &str,
+        proposal_id: u64,
+        vote: VoteOption,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::SetDefaultVote { proposal_id, vote },
+            &[],
+        )
+    }
+
+    pub fn cast_vote(&mut self, sender: &str, proposal_id: u64) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::CastVote { proposal_id },
             &[],
         )
     }
@@ -332,7 +766,23 @@ impl Suite {
         self.app.execute_contract(
             Addr::unchecked(sender),
             self.contract.clone(),
-            &ExecuteMsg::Delegate {},
+            &ExecuteMsg::Delegate { referrer: None },
+            &[amount],
+        )
+    }
+
+    pub fn delegate_with_referrer(
+        &mut self,
+        sender: &str,
+        amount: Coin,
+        referrer: &str,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Delegate {
+                referrer: Some(referrer.to_owned()),
+            },
             &[amount],
         )
     }
@@ -350,7 +800,27 @@ impl Suite {
         self.app.execute_contract(
             Addr::unchecked(sender),
             self.contract.clone(),
-            &ExecuteMsg::UndelegateAll {},
+            &ExecuteMsg::UndelegateAll { resumable: None },
+            &[],
+        )
+    }
+
+    pub fn undelegate_all_resumable(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::UndelegateAll {
+                resumable: Some(true),
+            },
+            &[],
+        )
+    }
+
+    pub fn resume(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Resume {},
             &[],
         )
     }
@@ -397,6 +867,17 @@ impl Suite {
         sender: &str,
         addr: &str,
         expires: u64,
+    ) -> AnyResult<AppResponse> {
+        self.update_allowed_addr_with_split(sender, addr, expires, None, None)
+    }
+
+    pub fn update_allowed_addr_with_split(
+        &mut self,
+        sender: &str,
+        addr: &str,
+        expires: u64,
+        commission_ratio: Option<Decimal>,
+        commission_cap: Option<Uint128>,
     ) -> AnyResult<AppResponse> {
         self.app.execute_contract(
             Addr::unchecked(sender),
@@ -404,6 +885,8 @@ impl Suite {
             &ExecuteMsg::UpdateAllowedAddr {
                 address: addr.into(),
                 expires,
+                commission_ratio,
+                commission_cap,
             },
             &[],
         )
@@ -429,6 +912,59 @@ impl Suite {
         )
     }
 
+    pub fn rebalance(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Rebalance {},
+            &[],
+        )
+    }
+
+    pub fn slash(&mut self, validator: &str, percentage: Decimal) -> AnyResult<AppResponse> {
+        self.app.sudo(SudoMsg::Staking(StakingSudo::Slash {
+            validator: validator.to_owned(),
+            percentage,
+        }))
+    }
+
+    pub fn withdraw_commission(&mut self, sender: &str, amount: u128) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::WithdrawCommission {
+                amount: amount.into(),
+            },
+            &[],
+        )
+    }
+
+    pub fn remove_validator(
+        &mut self,
+        sender: &str,
+        validator: &str,
+        mode: RemovalMode,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::RemoveValidator {
+                validator: validator.to_owned(),
+                mode,
+            },
+            &[],
+        )
+    }
+
+    pub fn rebond(&mut self, sender: &str) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            Addr::unchecked(sender),
+            self.contract.clone(),
+            &ExecuteMsg::Rebond {},
+            &[],
+        )
+    }
+
     pub fn query_config(&self) -> AnyResult<Config> {
         let response: ConfigResponse = self
             .app
@@ -455,6 +991,19 @@ impl Suite {
         Ok(response.delegated[0].clone())
     }
 
+    pub fn query_yield_recipient(
+        &self,
+        sender: impl Into<String>,
+    ) -> AnyResult<Option<YieldRecipient>> {
+        let response: DelegatedResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::Delegated {
+                sender: sender.into(),
+            },
+        )?;
+        Ok(response.yield_recipient)
+    }
+
     pub fn query_total_delegated(&self) -> AnyResult<TotalDelegatedResponse> {
         let response: TotalDelegatedResponse = self
             .app
@@ -471,6 +1020,14 @@ impl Suite {
         Ok(response.rewards[0].clone())
     }
 
+    pub fn query_rewards_by_validator(&self) -> AnyResult<Vec<ValidatorRewards>> {
+        let response: RewardsByValidatorResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::RewardsByValidator {})?;
+        Ok(response.validators)
+    }
+
     pub fn query_last_payment_block(&self) -> AnyResult<u64> {
         let response: LastPaymentBlockResponse = self
             .app
@@ -518,6 +1075,179 @@ impl Suite {
         Ok(response.expires)
     }
 
+    pub fn query_allowed_addr_details(&self, address: &str) -> AnyResult<AllowedAddrResponse> {
+        let response: AllowedAddrResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::AllowedAddr {
+                address: address.to_string(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_referrer_stats(&self, referrer: &str) -> AnyResult<ReferrerStatsResponse> {
+        let response: ReferrerStatsResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::ReferrerStats {
+                referrer: referrer.to_owned(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_commission_stats(
+        &self,
+        address: Option<&str>,
+    ) -> AnyResult<CommissionStatsResponse> {
+        let response: CommissionStatsResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::CommissionStats {
+                address: address.map(str::to_owned),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_proposal_votes(&self, proposal_id: u64) -> AnyResult<ProposalVotesResponse> {
+        let response: ProposalVotesResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::ProposalVotes { proposal_id },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_status(&self) -> AnyResult<ContractStatus> {
+        let response: StatusResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::Status {})?;
+        Ok(response.status)
+    }
+
+    pub fn query_stake_at_height(
+        &self,
+        address: &str,
+        height: impl Into<Option<u64>>,
+    ) -> AnyResult<Uint128> {
+        let response: StakeAtHeightResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::StakeAtHeight {
+                address: address.to_owned(),
+                height: height.into(),
+            },
+        )?;
+        Ok(response.stake)
+    }
+
+    pub fn query_total_at_height(&self, height: impl Into<Option<u64>>) -> AnyResult<Uint128> {
+        let response: TotalAtHeightResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::TotalAtHeight {
+                height: height.into(),
+            },
+        )?;
+        Ok(response.total)
+    }
+
+    pub fn query_voting_power_at_height(
+        &self,
+        address: &str,
+        height: impl Into<Option<u64>>,
+    ) -> AnyResult<VotingPowerAtHeightResponse> {
+        let response = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::VotingPowerAtHeight {
+                address: address.to_owned(),
+                height: height.into(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_total_power_at_height(
+        &self,
+        height: impl Into<Option<u64>>,
+    ) -> AnyResult<TotalPowerAtHeightResponse> {
+        let response = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::TotalPowerAtHeight {
+                height: height.into(),
+            },
+        )?;
+        Ok(response)
+    }
+
+    pub fn query_dao(&self) -> AnyResult<Addr> {
+        let response = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::Dao {})?;
+        Ok(response)
+    }
+
+    pub fn query_info(&self) -> AnyResult<ContractVersion> {
+        let response: InfoResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::Info {})?;
+        Ok(response.info)
+    }
+
+    pub fn query_admin_history(
+        &self,
+        start_after: impl Into<Option<u64>>,
+        limit: impl Into<Option<u32>>,
+    ) -> AnyResult<Vec<(u64, AdminAction)>> {
+        let response: AdminHistoryResponse = self.app.wrap().query_wasm_smart(
+            self.contract.clone(),
+            &QueryMsg::AdminHistory {
+                start_after: start_after.into(),
+                limit: limit.into(),
+            },
+        )?;
+        Ok(response.entries)
+    }
+
+    pub fn query_pending_proposals(&self) -> AnyResult<Vec<(u64, PendingProposal)>> {
+        let response: PendingProposalsResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::PendingProposals {})?;
+        Ok(response.proposals)
+    }
+
+    pub fn query_blocked_validators(&self) -> AnyResult<Vec<(String, Expiration)>> {
+        let response: BlockedValidatorsResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::BlockedValidators {})?;
+        Ok(response.validators)
+    }
+
+    pub fn query_allocation_drift(&self) -> AnyResult<AllocationDriftResponse> {
+        let response: AllocationDriftResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::AllocationDrift {})?;
+        Ok(response)
+    }
+
+    pub fn query_unbonding_entries(&self) -> AnyResult<Vec<(String, u64)>> {
+        let response: UnbondingEntriesResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::UnbondingEntries {})?;
+        Ok(response.entries)
+    }
+
+    pub fn query_pending_rebonds(&self) -> AnyResult<Vec<RebondDetails>> {
+        let response: PendingRebondsResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(self.contract.clone(), &QueryMsg::PendingRebonds {})?;
+        Ok(response.rebonds)
+    }
+
     pub fn migrate<T: Serialize>(
         &mut self,
         sender: &str,
@@ -1,12 +1,12 @@
-use super::suite::SuiteBuilder;
+use super::suite::{Suite, SuiteBuilder};
 
 use cosmwasm_std::{coin, coins, Decimal, Timestamp, Uint128};
 use cw_utils::Expiration;
 
 use crate::{
-    msg::{DelegateResponse, TotalDelegatedResponse},
+    msg::{ConfigUpdate, DelegateResponse, TotalDelegatedResponse},
     multitest::suite::{validator_list, TWENTY_EIGHT_DAYS},
-    state::Config,
+    state::{CommissionEarnings, Config, PayoutMode},
     ContractError,
 };
 use test_case::test_case;
@@ -260,3 +260,280 @@ fn transfer_with_allowed_address() {
         }
     );
 }
+
+#[test]
+fn transfer_with_custom_commission_split() {
+    let user1 = ("user1", 50_000_000u128);
+    let user2 = "user2";
+    let allowed = "allowed_address";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1.0, &coins(user1.1, "ujuno"))
+        .with_restake_commission(Decimal::percent(10))
+        .build();
+
+    suite.delegate(user1.0, coin(user1.1, "ujuno")).unwrap();
+    suite.advance_height(500);
+    suite.restake(suite.owner().as_str()).unwrap();
+
+    let expires = suite
+        .app
+        .block_info()
+        .time
+        .plus_seconds(TWENTY_EIGHT_DAYS + 1)
+        .seconds();
+
+    let err: ContractError = suite
+        .update_allowed_addr_with_split(
+            suite.owner().as_str(),
+            allowed,
+            expires,
+            Some(Decimal::percent(150)),
+            None,
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::InvalidCommissionRatio {
+            ratio: Decimal::percent(150)
+        }
+    );
+
+    suite
+        .update_allowed_addr_with_split(
+            suite.owner().as_str(),
+            allowed,
+            expires,
+            Some(Decimal::percent(80)),
+            Some(Uint128::new(1_500_000)),
+        )
+        .unwrap();
+
+    // 1_000_000 commission, 80% of it goes to the allowed address
+    suite
+        .transfer(
+            user1.0,
+            user2,
+            10_000_000u128.into(),
+            Some(allowed.to_string()),
+        )
+        .unwrap();
+    assert_eq!(
+        suite.query_delegated(allowed).unwrap().total_staked.u128(),
+        800_000u128
+    );
+    assert_eq!(
+        suite
+            .query_delegated(suite.treasury())
+            .unwrap()
+            .total_staked
+            .u128(),
+        200_000u128
+    );
+
+    // only 700_000 is left until the cap, the rest goes to the treasury
+    suite
+        .transfer(
+            user1.0,
+            user2,
+            10_000_000u128.into(),
+            Some(allowed.to_string()),
+        )
+        .unwrap();
+    assert_eq!(
+        suite.query_delegated(allowed).unwrap().total_staked.u128(),
+        1_500_000u128
+    );
+    assert_eq!(
+        suite
+            .query_delegated(suite.treasury())
+            .unwrap()
+            .total_staked
+            .u128(),
+        500_000u128
+    );
+    assert_eq!(
+        suite.query_delegated(user2).unwrap().total_staked.u128(),
+        18_000_000u128
+    );
+
+    let details = suite.query_allowed_addr_details(allowed).unwrap();
+    assert_eq!(details.commission_ratio, Decimal::percent(80));
+    assert_eq!(details.commission_cap, Some(Uint128::new(1_500_000)));
+    assert_eq!(details.commission_earned, Uint128::new(1_500_000));
+
+    let stats = suite.query_commission_stats(Some(allowed)).unwrap();
+    assert_eq!(
+        stats.address,
+        Some(CommissionEarnings {
+            amount: Uint128::new(1_500_000),
+            count: 2
+        })
+    );
+    assert_eq!(stats.referral_commission, Some(Uint128::zero()));
+    assert_eq!(stats.treasury.transfers, Uint128::new(500_000));
+    // restake commission is paid out liquid
+    assert_eq!(
+        stats.treasury.restakes,
+        suite
+            .app
+            .wrap()
+            .query_balance(suite.treasury(), "ujuno")
+            .unwrap()
+            .amount
+    );
+    assert_eq!(suite.query_commission_stats(None).unwrap().address, None);
+
+    // updating the deal keeps what has been earned so far
+    suite
+        .update_allowed_addr(suite.owner().as_str(), allowed, expires)
+        .unwrap();
+    let details = suite.query_allowed_addr_details(allowed).unwrap();
+    assert_eq!(details.commission_ratio, Decimal::percent(50));
+    assert_eq!(details.commission_cap, None);
+    assert_eq!(details.commission_earned, Uint128::new(1_500_000));
+}
+
+#[test]
+fn commission_split_between_treasury_recipients() {
+    let user1 = ("user1", 50_000_000u128);
+    let user2 = "user2";
+    let (team, ops) = ("team", "ops");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1.0, &coins(user1.1, "ujuno"))
+        .with_restake_commission(Decimal::percent(10))
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_config(
+            owner.as_str(),
+            None,
+            vec![
+                (team.to_owned(), Decimal::percent(60)),
+                (ops.to_owned(), Decimal::percent(40)),
+            ],
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+    suite.delegate(user1.0, coin(user1.1, "ujuno")).unwrap();
+    suite.advance_height(500);
+    let commission = Decimal::percent(10) * suite.query_reward().unwrap().amount;
+    suite.restake(owner.as_str()).unwrap();
+
+    let team_share = Decimal::percent(60) * commission;
+    let balance = |suite: &Suite, address: &str| {
+        suite
+            .app
+            .wrap()
+            .query_balance(address, "ujuno")
+            .unwrap()
+            .amount
+    };
+    assert_eq!(balance(&suite, team), team_share);
+    assert_eq!(balance(&suite, ops), commission - team_share);
+
+    // 1_000_000 transfer commission, all of it for the treasury
+    suite
+        .transfer(user1.0, user2, 10_000_000u128.into(), None)
+        .unwrap();
+    assert_eq!(
+        suite.query_delegated(team).unwrap().total_staked.u128(),
+        600_000u128
+    );
+    assert_eq!(
+        suite.query_delegated(ops).unwrap().total_staked.u128(),
+        400_000u128
+    );
+}
+
+#[test]
+fn commission_payout_modes() {
+    let user1 = ("user1", 50_000_000u128);
+    let user2 = "user2";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1.0, &coins(user1.1, "ujuno"))
+        .with_restake_commission(Decimal::percent(10))
+        .build();
+    let owner = suite.owner();
+    let treasury = suite.treasury();
+
+    suite
+        .update_config_with(
+            owner.as_str(),
+            ConfigUpdate {
+                restake_commission_payout: Some(PayoutMode::Stake),
+                transfer_commission_payout: Some(PayoutMode::Liquid),
+                ..ConfigUpdate::default()
+            },
+        )
+        .unwrap();
+
+    suite.delegate(user1.0, coin(user1.1, "ujuno")).unwrap();
+    suite.advance_height(500);
+    let reward = suite.query_reward().unwrap().amount;
+    let commission = Decimal::percent(10) * reward;
+    suite.restake(owner.as_str()).unwrap();
+
+    // restake commission is credited as stake and delegated with the reward
+    assert_eq!(
+        suite
+            .query_delegated(treasury.as_str())
+            .unwrap()
+            .total_staked,
+        commission
+    );
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(treasury.as_str(), "ujuno")
+            .unwrap()
+            .amount,
+        Uint128::zero()
+    );
+    let delegated: Uint128 = suite
+        .query_all_delegations()
+        .unwrap()
+        .iter()
+        .map(|delegation| delegation.amount.amount)
+        .sum();
+    assert_eq!(delegated, Uint128::new(user1.1) + reward);
+
+    // transfer commission waits for the next batch to be paid liquid
+    suite
+        .transfer(user1.0, user2, 10_000_000u128.into(), None)
+        .unwrap();
+    assert_eq!(
+        suite.query_pending_claims(treasury.as_str()).unwrap(),
+        Uint128::new(1_000_000)
+    );
+
+    // only treasury recipients may withdraw commission
+    let err: ContractError = suite
+        .withdraw_commission(user2, 1_000)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // stake commission gets unbonded like any pending claim
+    suite
+        .withdraw_commission(treasury.as_str(), commission.u128())
+        .unwrap();
+    assert_eq!(
+        suite.query_pending_claims(treasury.as_str()).unwrap(),
+        Uint128::new(1_000_000) + commission
+    );
+    assert_eq!(
+        suite
+            .query_delegated(treasury.as_str())
+            .unwrap()
+            .total_staked,
+        Uint128::zero()
+    );
+}
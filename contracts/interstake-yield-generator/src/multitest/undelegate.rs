@@ -1,10 +1,11 @@
 use super::suite::{SuiteBuilder, TWENTY_EIGHT_DAYS};
 
-use crate::msg::DelegateResponse;
-use crate::multitest::suite::validator_list;
-use crate::state::ClaimDetails;
+use crate::contract::utils::{plan_undelegations, ValidatorUnbonding};
+use crate::msg::{DelegateResponse, RemovalMode};
+use crate::multitest::suite::{validator_list, VALIDATOR_1, VALIDATOR_2};
+use crate::state::{ClaimDetails, ContractStatus};
 use crate::{error::ContractError, multitest::suite::FOUR_DAYS};
-use cosmwasm_std::{coin, coins, Addr, Uint128};
+use cosmwasm_std::{coin, coins, Addr, Decimal, Uint128};
 use cw_utils::{Duration, Expiration};
 use test_case::test_case;
 
@@ -390,6 +391,11 @@ fn undelegate_all(i: u32, n_users: u32) {
     let res = suite.undelegate_all(users[0].as_str()).unwrap_err();
     assert_eq!(ContractError::Unauthorized {}, res.downcast().unwrap());
 
+    // pending rewards are withdrawn and shared by stake
+    let reward = suite.query_reward().unwrap().amount;
+    let share = reward.multiply_ratio(1u128, n_users as u128);
+    assert!(!share.is_zero());
+
     let res = suite.undelegate_all(suite.owner().as_str());
     assert!(res.is_ok(), "undelegate_all by owner failed: {res:?}");
 
@@ -403,10 +409,18 @@ fn undelegate_all(i: u32, n_users: u32) {
         let current_time = suite.app.block_info().time;
         assert_eq!(
             suite.query_claims(user.as_str()).unwrap(),
-            vec![ClaimDetails {
-                amount: coin(700, "ujuno"),
-                release_timestamp: Expiration::AtTime(current_time.plus_seconds(TWENTY_EIGHT_DAYS))
-            }]
+            vec![
+                ClaimDetails {
+                    amount: coin(700, "ujuno"),
+                    release_timestamp: Expiration::AtTime(
+                        current_time.plus_seconds(TWENTY_EIGHT_DAYS)
+                    )
+                },
+                ClaimDetails {
+                    amount: coin(share.u128(), "ujuno"),
+                    release_timestamp: Expiration::AtTime(current_time)
+                }
+            ]
         );
     }
 
@@ -428,7 +442,417 @@ fn undelegate_all(i: u32, n_users: u32) {
                 .query_balance(user.as_str(), "ujuno")
                 .unwrap()
                 .amount,
-            Uint128::new(1000)
+            Uint128::new(1000) + share
         );
     }
 }
+
+#[test]
+fn wind_down_after_undelegate_all() {
+    let (user1, user2) = ("user1", "user2");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(2000, "ujuno"))
+        .with_funds(user2, &coins(1000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+    assert_eq!(suite.query_status().unwrap(), ContractStatus::Active);
+
+    suite.delegate(user1, coin(1000, "ujuno")).unwrap();
+    suite.delegate(user2, coin(500, "ujuno")).unwrap();
+    let err = suite.resume(owner.as_str()).unwrap_err();
+    assert_eq!(ContractError::CannotResume {}, err.downcast().unwrap());
+    suite.advance_time(TWENTY_EIGHT_DAYS);
+
+    let reward = suite.query_reward().unwrap().amount;
+    assert!(!reward.is_zero());
+    suite.undelegate_all_resumable(owner.as_str()).unwrap();
+    assert_eq!(suite.query_status().unwrap(), ContractStatus::WindingDown);
+
+    // rewards withdrawn by undelegating are claimable right away, split by stake
+    let now = suite.app.block_info().time;
+    let unbonded_at = Expiration::AtTime(now.plus_seconds(TWENTY_EIGHT_DAYS));
+    let share1 = reward.multiply_ratio(1000u128, 1500u128);
+    let share2 = reward.multiply_ratio(500u128, 1500u128);
+    assert_eq!(
+        suite.query_claims(user1).unwrap(),
+        vec![
+            ClaimDetails {
+                amount: coin(1000, "ujuno"),
+                release_timestamp: unbonded_at
+            },
+            ClaimDetails {
+                amount: coin(share1.u128(), "ujuno"),
+                release_timestamp: Expiration::AtTime(now)
+            }
+        ]
+    );
+    assert_eq!(
+        suite.query_claims(user2).unwrap(),
+        vec![
+            ClaimDetails {
+                amount: coin(500, "ujuno"),
+                release_timestamp: unbonded_at
+            },
+            ClaimDetails {
+                amount: coin(share2.u128(), "ujuno"),
+                release_timestamp: Expiration::AtTime(now)
+            }
+        ]
+    );
+    suite.claim(user1).unwrap();
+    suite.claim(user2).unwrap();
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(user1, "ujuno")
+            .unwrap()
+            .amount,
+        Uint128::new(1000) + share1
+    );
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(user2, "ujuno")
+            .unwrap()
+            .amount,
+        Uint128::new(500) + share2
+    );
+
+    // deposits, transfers and rebonds are rejected while winding down
+    let err = suite.delegate(user1, coin(1000, "ujuno")).unwrap_err();
+    assert_eq!(ContractError::NotActive {}, err.downcast().unwrap());
+    let err = suite.rebond(user1).unwrap_err();
+    assert_eq!(ContractError::NotActive {}, err.downcast().unwrap());
+    let err = suite
+        .transfer(user1, user2, Uint128::new(1), None)
+        .unwrap_err();
+    assert_eq!(ContractError::NotActive {}, err.downcast().unwrap());
+    let err = suite.undelegate_all(owner.as_str()).unwrap_err();
+    assert_eq!(ContractError::NotActive {}, err.downcast().unwrap());
+    suite.restake(owner.as_str()).unwrap();
+
+    let err = suite.resume(user1).unwrap_err();
+    assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+    suite.resume(owner.as_str()).unwrap();
+    assert_eq!(suite.query_status().unwrap(), ContractStatus::Active);
+    suite.delegate(user1, coin(1000, "ujuno")).unwrap();
+
+    // without `resumable`, winding down is final
+    suite.undelegate_all(owner.as_str()).unwrap();
+    assert_eq!(suite.query_status().unwrap(), ContractStatus::Terminated);
+    let err = suite.resume(owner.as_str()).unwrap_err();
+    assert_eq!(ContractError::CannotResume {}, err.downcast().unwrap());
+}
+
+#[test]
+fn undelegate_all_pays_out_pending_rebonds() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1000, "ujuno"))
+        .build();
+    let owner = suite.owner();
+
+    suite
+        .update_validator_list(owner.as_str(), validator_list(2))
+        .unwrap();
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+    suite.advance_time(FOUR_DAYS);
+
+    // half of the stake is unbonding to be delegated back later
+    suite
+        .remove_validator(owner.as_str(), VALIDATOR_2, RemovalMode::Undelegate)
+        .unwrap();
+    assert_eq!(suite.query_pending_rebonds().unwrap().len(), 1);
+
+    let reward = suite.query_reward().unwrap().amount;
+    suite.undelegate_all(owner.as_str()).unwrap();
+    assert_eq!(suite.query_pending_rebonds().unwrap(), vec![]);
+    let stake = suite.query_all_delegations().unwrap();
+    assert_eq!(
+        stake.iter().map(|d| d.amount.amount.u128()).sum::<u128>(),
+        0u128
+    );
+
+    // the unbonding tokens are part of the claim of the whole stake
+    let now = suite.app.block_info().time;
+    assert_eq!(
+        suite.query_claims(user).unwrap(),
+        vec![
+            ClaimDetails {
+                amount: coin(1000, "ujuno"),
+                release_timestamp: Expiration::AtTime(now.plus_seconds(TWENTY_EIGHT_DAYS))
+            },
+            ClaimDetails {
+                amount: coin(reward.u128(), "ujuno"),
+                release_timestamp: Expiration::AtTime(now)
+            }
+        ]
+    );
+
+    suite.advance_time(TWENTY_EIGHT_DAYS);
+    suite.process_staking_queue().unwrap();
+    suite.claim(user).unwrap();
+    assert_eq!(
+        suite
+            .app
+            .wrap()
+            .query_balance(user, "ujuno")
+            .unwrap()
+            .amount,
+        Uint128::new(1000) + reward
+    );
+}
+
+#[test]
+fn unbonding_entries_are_limited_per_validator() {
+    let user = "user";
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user, &coins(1000, "ujuno"))
+        .with_max_entries(2)
+        .build();
+    let owner = suite.owner();
+
+    suite.delegate(user, coin(1000, "ujuno")).unwrap();
+    assert_eq!(
+        suite.query_unbonding_entries().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), 2)]
+    );
+
+    suite.undelegate(user, coin(100, "ujuno")).unwrap();
+    suite.batch_unbond(user).unwrap();
+    assert_eq!(
+        suite.query_unbonding_entries().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), 1)]
+    );
+
+    // cooldown with two entries is half of the unbonding period
+    suite.advance_time(TWENTY_EIGHT_DAYS / 2);
+    suite.undelegate(user, coin(100, "ujuno")).unwrap();
+    suite.batch_unbond(user).unwrap();
+    assert_eq!(
+        suite.query_unbonding_entries().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), 0)]
+    );
+
+    // undelegate all doesn't wait for the cooldown, but still can't exceed the limit
+    let err = suite.undelegate_all(owner.as_str()).unwrap_err();
+    assert_eq!(
+        ContractError::MaxUnbondingEntries {
+            validator: VALIDATOR_1.to_owned()
+        },
+        err.downcast().unwrap()
+    );
+
+    // first entry completes and frees a slot
+    suite.advance_time(TWENTY_EIGHT_DAYS / 2);
+    assert_eq!(
+        suite.query_unbonding_entries().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), 1)]
+    );
+    suite.undelegate_all(owner.as_str()).unwrap();
+    assert_eq!(
+        suite.query_unbonding_entries().unwrap(),
+        vec![(VALIDATOR_1.to_owned(), 0)]
+    );
+}
+
+fn validator_unbonding(
+    validator: &str,
+    weight: u64,
+    has_free_entry: bool,
+    delegated: u128,
+) -> ValidatorUnbonding {
+    ValidatorUnbonding {
+        validator: validator.to_owned(),
+        weight: Decimal::percent(weight),
+        has_free_entry,
+        delegated: Uint128::new(delegated),
+    }
+}
+
+#[test]
+fn plan_undelegations_reroutes_full_validator() {
+    let validators = vec![
+        validator_unbonding("validator1", 50, false, 500),
+        validator_unbonding("validator2", 30, true, 300),
+        validator_unbonding("validator3", 20, true, 200),
+    ];
+
+    let planned = plan_undelegations(Uint128::new(100), &validators).unwrap();
+    assert_eq!(
+        planned,
+        vec![
+            ("validator2".to_owned(), Uint128::new(80)),
+            ("validator3".to_owned(), Uint128::new(20)),
+        ]
+    );
+}
+
+#[test]
+fn plan_undelegations_caps_shares_at_delegation() {
+    let validators = vec![
+        validator_unbonding("validator1", 50, true, 100),
+        validator_unbonding("validator2", 30, true, 700),
+        validator_unbonding("validator3", 20, true, 200),
+    ];
+
+    let planned = plan_undelegations(Uint128::new(600), &validators).unwrap();
+    assert_eq!(
+        planned,
+        vec![
+            ("validator1".to_owned(), Uint128::new(100)),
+            ("validator2".to_owned(), Uint128::new(380)),
+            ("validator3".to_owned(), Uint128::new(120)),
+        ]
+    );
+
+    let err = plan_undelegations(Uint128::new(1100), &validators).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::NotEnoughToUndelegate {
+            wanted: Uint128::new(1100),
+            have: Uint128::new(1000)
+        }
+    );
+}
+
+#[test]
+fn plan_undelegations_refuses_when_no_room_left() {
+    let validators = vec![
+        validator_unbonding("validator1", 50, false, 500),
+        validator_unbonding("validator2", 30, true, 300),
+        validator_unbonding("validator3", 20, true, 200),
+    ];
+
+    let err = plan_undelegations(Uint128::new(1000), &validators).unwrap_err();
+    assert_eq!(
+        err,
+        ContractError::MaxUnbondingEntries {
+            validator: "validator1".to_owned()
+        }
+    );
+}
+
+#[test]
+fn early_exit_fee() {
+    let (user1, user2, user3) = ("user1", "user2", "user3");
+    let mut suite = SuiteBuilder::new()
+        .with_funds(user1, &coins(1_100_000, "ujuno"))
+        .with_funds(user2, &coins(1_000_000, "ujuno"))
+        .with_funds(user3, &coins(100_000, "ujuno"))
+        .with_early_exit_fee(Decimal::percent(10), 1_000)
+        .build();
+    let owner = suite.owner();
+    let treasury = suite.treasury();
+
+    // nothing staked, nothing to pay
+    let simulation = suite.query_simulate_undelegate(user1, 100_000).unwrap();
+    assert_eq!(simulation.fee, Uint128::zero());
+    assert_eq!(simulation.received, Uint128::new(100_000));
+
+    suite.delegate(user1, coin(1_000_000, "ujuno")).unwrap();
+    suite.advance_time(FOUR_DAYS);
+    suite.restake(owner.as_str()).unwrap();
+
+    suite.delegate(user2, coin(1_000_000, "ujuno")).unwrap();
+    let start_height = suite.block_height();
+    suite.advance_height(100);
+    suite.restake(owner.as_str()).unwrap();
+
+    // the reward restaked since counts as older stake, which leaves first
+    let earned = suite.query_delegated(user2).unwrap().total_earnings;
+    let fee = Decimal::percent(10) * (Uint128::new(500_000) - earned);
+    let simulation = suite.query_simulate_undelegate(user2, 500_000).unwrap();
+    assert_eq!(simulation.fee, fee);
+    assert_eq!(simulation.received, Uint128::new(500_000) - fee);
+    assert_eq!(simulation.fee_free_height, start_height + 1_000);
+
+    // half of the fee is staked for the treasury, the claim is reduced by the whole fee
+    suite.undelegate(user2, coin(500_000, "ujuno")).unwrap();
+    assert_eq!(
+        suite.query_pending_claims(user2).unwrap(),
+        Uint128::new(500_000) - fee
+    );
+    let treasury_fee = Decimal::percent(50) * fee;
+    assert_eq!(
+        suite
+            .query_delegated(treasury.as_str())
+            .unwrap()
+            .total_staked,
+        treasury_fee
+    );
+
+    // older stake and withdrawn commission leave for free
+    let simulation = suite.query_simulate_undelegate(user1, 100_000).unwrap();
+    assert_eq!(simulation.fee, Uint128::zero());
+    suite.undelegate(user1, coin(100_000, "ujuno")).unwrap();
+    assert_eq!(
+        suite.query_pending_claims(user1).unwrap(),
+        Uint128::new(100_000)
+    );
+    suite
+        .withdraw_commission(treasury.as_str(), treasury_fee.u128())
+        .unwrap();
+    assert_eq!(
+        suite.query_pending_claims(treasury.as_str()).unwrap(),
+        treasury_fee
+    );
+
+    // once the fee period is over user2 leaves for free
+    suite.advance_height(1_000);
+    let remaining = suite.query_delegated(user2).unwrap().total_staked;
+    let simulation = suite
+        .query_simulate_undelegate(user2, remaining.u128())
+        .unwrap();
+    assert_eq!(simulation.fee, Uint128::zero());
+    suite
+        .undelegate(user2, coin(remaining.u128(), "ujuno"))
+        .unwrap();
+
+    // the other half goes to user1 as the only staker left with the next restake
+    let before = suite.query_delegated(user1).unwrap();
+    suite.advance_height(100);
+    suite.restake(owner.as_str()).unwrap();
+    let after = suite.query_delegated(user1).unwrap();
+    assert_eq!(
+        (after.total_staked - before.total_staked) - (after.total_earnings - before.total_earnings),
+        fee - treasury_fee
+    );
+    assert_eq!(
+        suite.query_total_delegated().unwrap().amount.amount,
+        after.total_staked
+    );
+
+    // a top-up only pays the fee on itself, once the older stake is gone
+    suite.delegate(user1, coin(100_000, "ujuno")).unwrap();
+    let top_up_height = suite.block_height();
+    let simulation = suite.query_simulate_undelegate(user1, 100_000).unwrap();
+    assert_eq!(simulation.fee, Uint128::zero());
+    let staked = suite.query_delegated(user1).unwrap().total_staked;
+    let simulation = suite
+        .query_simulate_undelegate(user1, staked.u128())
+        .unwrap();
+    assert_eq!(simulation.fee, Uint128::new(10_000));
+    assert_eq!(simulation.fee_free_height, top_up_height + 1_000);
+
+    // young stake keeps its fee period when it's transferred
+    suite.delegate(user3, coin(100_000, "ujuno")).unwrap();
+    suite.advance_height(100);
+    suite.restake(owner.as_str()).unwrap();
+    suite.advance_height(100);
+    suite.restake(owner.as_str()).unwrap();
+    let earned = suite.query_delegated(user3).unwrap().total_earnings;
+    suite
+        .transfer(user3, user1, Uint128::new(100_000), None)
+        .unwrap();
+    let staked = suite.query_delegated(user1).unwrap().total_staked;
+    let simulation = suite
+        .query_simulate_undelegate(user1, staked.u128())
+        .unwrap();
+    assert_eq!(
+        simulation.fee,
+        Decimal::percent(10) * (Uint128::new(200_000) - earned)
+    );
+}
@@ -2,19 +2,83 @@ use cw_utils::{Duration, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Coin, Decimal, StdResult, Storage, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Coin, Decimal, StdResult, Storage, Timestamp, Uint128, VoteOption};
+use cw_storage_plus::{Item, Map, SnapshotItem, SnapshotMap, Strategy};
+
+use crate::msg::ConfigUpdate;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Config {
     pub owner: Addr,
-    pub treasury: Addr,
+    /// Recipients of the treasury commission with their weights, which sum to 1
+    pub treasury: Vec<(Addr, Decimal)>,
     pub restake_commission: Decimal,
     pub transfer_commission: Decimal,
     pub denom: String,
     pub unbonding_period: Duration,
     pub min_unbonding_cooldown: Duration,
+    /// Maximum number of simultaneous unbonding entries per validator
+    pub max_entries: u64,
+    /// Allocation drift above which restaked rewards go to underweight validators
+    /// and `Rebalance` can be called
+    pub drift_threshold: Decimal,
+    /// Share of a referred user's restake commission paid to the referrer
+    pub referral_commission: Decimal,
+    /// How long a referrer earns on the users it referred
+    pub referral_period: Duration,
+    /// How restake commission is paid to the treasury and referrers
+    pub restake_commission_payout: PayoutMode,
+    /// How transfer commission is paid to the treasury and commission addresses
+    pub transfer_commission_payout: PayoutMode,
+    /// Delay before a proposed config or validator list change can be executed;
+    /// when non-zero, those changes can't be applied directly
+    pub timelock_delay: Duration,
+    /// Share of the total stake which has to vote on a proposal;
+    /// below it, the owner's default vote is cast instead
+    pub vote_quorum: Decimal,
+    /// Lock durations available to `DelegateLocked` and their reward multipliers
+    pub lock_tiers: Vec<LockTier>,
+    /// Share of a lock forfeited to the treasury when it's unlocked before expiring
+    pub early_unlock_penalty: Decimal,
+    /// Share of undelegated stake charged when the stake is younger than `early_exit_blocks`
+    pub early_exit_fee: Decimal,
+    pub early_exit_blocks: u64,
+    /// Part of the early exit fee paid to the treasury; the rest goes to remaining stakers
+    pub early_exit_treasury_share: Decimal,
+    /// Limit of `TOTAL`, zero means no cap
+    pub deposit_cap: Uint128,
+    /// Limit of a single address' stake, zero means no cap
+    pub address_cap: Uint128,
+    pub min_delegation: Uint128,
+    pub min_undelegation: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Lock {
+    pub amount: Uint128,
+    pub multiplier: Decimal,
+    pub start_height: u64,
+    pub unlocks_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LockTier {
+    /// Lock duration in seconds
+    pub duration: u64,
+    /// Reward weight of locked tokens; at least 1.0
+    pub multiplier: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutMode {
+    /// Sent as tokens; transfer commission gets unbonded with the next batch first
+    Liquid,
+    /// Credited as auto-compounding stake
+    Stake,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
@@ -34,6 +98,16 @@ pub struct StakeDetails {
 }
 
 impl StakeDetails {
+    /// Whole stake, including partial stakes which don't earn the full reward yet
+    pub fn staked(&self) -> Uint128 {
+        self.total.amount
+            + self
+                .partials
+                .iter()
+                .map(|stake| stake.amount.amount)
+                .sum::<Uint128>()
+    }
+
     /// Check all partial weight stakes if should be counted as full weighted stake
     pub fn consolidate_partials(&mut self, storage: &dyn Storage) -> StdResult<()> {
         let last_payment_block = LAST_PAYMENT_BLOCK.load(storage)?;
@@ -55,6 +129,152 @@ pub struct ClaimDetails {
     pub amount: Coin,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RedelegationDetails {
+    pub src_validator: String,
+    pub amount: Coin,
+    pub completion: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RebondDetails {
+    /// Validator which receives the tokens once they are released;
+    /// split by current weights if not set
+    pub validator: Option<String>,
+    pub release_timestamp: Expiration,
+    pub amount: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AllowedAddress {
+    pub expires: Expiration,
+    /// Share of the transfer commission paid to the address; the rest goes to the treasury
+    pub commission_ratio: Decimal,
+    /// Maximum cumulative commission the address can earn; the rest goes to the treasury
+    pub commission_cap: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TreasuryCommission {
+    /// Restake commission received by the treasury
+    pub restakes: Uint128,
+    /// Transfer commission received by the treasury
+    pub transfers: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct CommissionEarnings {
+    /// Transfer commission earned so far
+    pub amount: Uint128,
+    /// Number of transfers the commission was earned on
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct CommissionBaseline {
+    /// Commission before the first change in the current window
+    pub commission: Decimal,
+    /// Height at which the window started
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Referral {
+    pub referrer: Addr,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReferrerStats {
+    /// Number of users attributed to the referrer
+    pub referred_users: u64,
+    /// Restake commission paid to the referrer so far
+    pub commission_earned: Uint128,
+}
+
+/// Share of a user's rewards which goes to another address
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct YieldRecipient {
+    pub recipient: Addr,
+    pub ratio: Decimal,
+    pub payout: PayoutMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardMode {
+    /// Rewards are added to the stake
+    #[default]
+    Compound,
+    /// Rewards accrue as tokens claimable with `ClaimRewards {}`
+    Liquid,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    #[default]
+    Active,
+    /// Everything was undelegated; deposits and transfers are disabled until `Resume {}`
+    WindingDown,
+    /// Everything was undelegated for good
+    Terminated,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingChange {
+    Config(ConfigUpdate),
+    ValidatorList(Vec<(String, Decimal)>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingProposal {
+    pub proposer: Addr,
+    pub change: PendingChange,
+    /// Proposal can be executed by anyone once this expires
+    pub execute_after: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct AdminAction {
+    pub height: u64,
+    pub time: Timestamp,
+    pub sender: Addr,
+    /// Address that executed a timelocked change proposed by `sender`
+    pub executor: Option<Addr>,
+    pub action: String,
+    /// JSON encoded value before the action, if any
+    pub old_value: Option<String>,
+    /// JSON encoded value after the action, if any
+    pub new_value: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct YieldEntry {
+    pub height: u64,
+    pub time: Timestamp,
+    /// Restaked reward, after commission
+    pub reward: Uint128,
+    pub commission: Uint128,
+    pub total_before: Uint128,
+    pub total_after: Uint128,
+    /// Blocks since the previous restake
+    pub blocks: u64,
+}
+
 pub const CONFIG: Item<Config> = Item::new("config");
 // Total amount of staked tokens
 // TODO: Replace with Vec<Coin>
@@ -62,10 +282,102 @@ pub const TOTAL: Item<Coin> = Item::new("total");
 pub const LAST_PAYMENT_BLOCK: Item<u64> = Item::new("last_payment_block");
 
 pub const STAKE_DETAILS: Map<&Addr, StakeDetails> = Map::new("stake_details");
+/// Whole stake of every address by height; kept in sync with `STAKE_DETAILS`
+pub const STAKE_SNAPSHOTS: SnapshotMap<&Addr, Uint128> = SnapshotMap::new(
+    "stake_snapshots",
+    "stake_snapshots__checkpoints",
+    "stake_snapshots__changelog",
+    Strategy::EveryBlock,
+);
+/// Total stake by height; kept in sync with `TOTAL`
+pub const TOTAL_STAKE_SNAPSHOT: SnapshotItem<Uint128> = SnapshotItem::new(
+    "total_stake_snapshot",
+    "total_stake_snapshot__checkpoints",
+    "total_stake_snapshot__changelog",
+    Strategy::EveryBlock,
+);
+/// Sum of `STAKE_SNAPSHOTS` by height, the total voting power. Unlike `TOTAL` it leaves out
+/// undelegations and commissions waiting in `PENDING_CLAIMS`.
+pub const TOTAL_POWER_SNAPSHOT: SnapshotItem<Uint128> = SnapshotItem::new(
+    "total_power_snapshot",
+    "total_power_snapshot__checkpoints",
+    "total_power_snapshot__changelog",
+    Strategy::EveryBlock,
+);
 
 pub const LATEST_UNBONDING: Item<Expiration> = Item::new("latest_unbonding");
 pub const PENDING_CLAIMS: Map<&Addr, Uint128> = Map::new("pending_claims");
 pub const UNBONDING_CLAIMS: Map<&Addr, Vec<ClaimDetails>> = Map::new("unbonding_claims");
 
 pub const VALIDATOR_LIST: Map<String, Decimal> = Map::new("validator_list");
-pub const ALLOWED_ADDRESSES: Map<&Addr, Expiration> = Map::new("allowed_addresses");
+pub const ALLOWED_ADDRESSES: Map<&Addr, AllowedAddress> = Map::new("allowed_addresses");
+pub const TREASURY_COMMISSION: Item<TreasuryCommission> = Item::new("treasury_commission");
+/// Transfer commission earned by every commission address
+pub const COMMISSION_EARNINGS: Map<&Addr, CommissionEarnings> = Map::new("commission_earnings");
+/// Start of the current change window of the "restake" and "transfer" commissions
+pub const COMMISSION_BASELINES: Map<&str, CommissionBaseline> = Map::new("commission_baselines");
+/// Referrer of every referred user, keyed by the user
+pub const REFERRALS: Map<&Addr, Referral> = Map::new("referrals");
+pub const REFERRER_STATS: Map<&Addr, ReferrerStats> = Map::new("referrer_stats");
+
+/// Redelegations received by a validator, keyed by the destination validator.
+/// Until they mature, the SDK refuses any redelegation from that validator.
+pub const REDELEGATIONS: Map<String, Vec<RedelegationDetails>> = Map::new("redelegations");
+/// Completion times of unbonding entries per validator; the SDK limits them to `max_entries`
+pub const UNBONDING_ENTRIES: Map<String, Vec<Expiration>> = Map::new("unbonding_entries");
+/// Tokens undelegated in place of a blocked redelegation, waiting to be delegated again
+pub const PENDING_REBONDS: Item<Vec<RebondDetails>> = Item::new("pending_rebonds");
+
+/// Timelocked owner changes, keyed by proposal id
+pub const PENDING_PROPOSALS: Map<u64, PendingProposal> = Map::new("pending_proposals");
+pub const PROPOSAL_COUNT: Item<u64> = Item::new("proposal_count");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct GovProposal {
+    /// Votes are weighted by the stake at the start of this block
+    pub height: u64,
+    pub voting_end: Timestamp,
+}
+
+/// Gov proposals opened for delegator votes by the owner
+pub const GOV_PROPOSALS: Map<u64, GovProposal> = Map::new("gov_proposals");
+/// Gov votes of delegators, keyed by proposal id and voter
+pub const VOTES: Map<(u64, &Addr), VoteOption> = Map::new("votes");
+/// Owner's vote per proposal, cast when delegators don't reach the quorum
+pub const DEFAULT_VOTES: Map<u64, VoteOption> = Map::new("default_votes");
+
+pub const STATUS: Item<ContractStatus> = Item::new("status");
+/// Stake of every user at the time of `UndelegateAll`; rewards collected while winding down
+/// are split by it
+pub const WIND_DOWN_SHARES: Map<&Addr, Uint128> = Map::new("wind_down_shares");
+
+/// Every restake, keyed by a sequential id
+pub const YIELD_HISTORY: Map<u64, YieldEntry> = Map::new("yield_history");
+pub const YIELD_HISTORY_COUNT: Item<u64> = Item::new("yield_history_count");
+/// Reward restaked for every user, keyed by user and the restake id from `YIELD_HISTORY`
+pub const USER_EARNINGS: Map<(&Addr, u64), Uint128> = Map::new("user_earnings");
+
+/// Users who don't compound their rewards; everyone else is in `RewardMode::Compound`
+pub const REWARD_MODES: Map<&Addr, RewardMode> = Map::new("reward_modes");
+/// Unclaimed rewards of users in `RewardMode::Liquid`, held by the contract
+pub const LIQUID_REWARDS: Map<&Addr, Uint128> = Map::new("liquid_rewards");
+
+pub const YIELD_RECIPIENTS: Map<&Addr, YieldRecipient> = Map::new("yield_recipients");
+
+/// Locked stake, keyed by owner and a sequential id
+pub const LOCKS: Map<(&Addr, u64), Lock> = Map::new("locks");
+pub const LOCK_COUNT: Item<u64> = Item::new("lock_count");
+
+/// Early exit fees waiting to be shared between stakers at the next restake
+pub const PENALTY_POOL: Item<Uint128> = Item::new("penalty_pool");
+/// Deposits of every user which may still be in the early exit fee period, oldest first.
+/// Unlike partial stakes they aren't consolidated, and they follow transferred stake.
+pub const YOUNG_DEPOSITS: Map<&Addr, Vec<Stake>> = Map::new("young_deposits");
+
+/// Contracts notified about every stake change
+pub const HOOKS: Item<Vec<Addr>> = Item::new("hooks");
+
+/// Append-only log of owner actions, keyed by a sequential id
+pub const ADMIN_HISTORY: Map<u64, AdminAction> = Map::new("admin_history");
+pub const ADMIN_HISTORY_COUNT: Item<u64> = Item::new("admin_history_count");